@@ -0,0 +1,79 @@
+// `cargo bench --bench backends`: steps every backend from the same seeded
+// noise, see cells::benchmark. it asserts that they all end up with the same
+// cell count, criterion does the timing.
+use bevy::{math::IVec3, tasks::TaskPool};
+use celluar_automata::{
+    cells::{self, leddoo::LeddooAtomic, SimBackend},
+    rule::Rule,
+};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+const SEED: u64 = 42;
+const STEPS: u32 = 20;
+
+fn backends(c: &mut Criterion) {
+    let rule = Rule::from_rulestring("B4/S4/5/M").unwrap();
+    let tasks = TaskPool::new();
+    let bounds = IVec3::splat(64);
+
+    let mut sims: Vec<(String, Box<dyn cells::Sim>)> = SimBackend::ALL.iter()
+        .map(|backend| (backend.name().into(), backend.build()))
+        .collect();
+    // checks the counts once across all backends, the runs below only see one.
+    for result in cells::benchmark(&mut sims, &rule, bounds, SEED, STEPS, &tasks) {
+        println!("{:>24}: {:8.2} steps/s, {} cells",
+            result.name, result.steps_per_second, result.cell_count);
+    }
+
+    let mut group = c.benchmark_group(format!("{} {} steps", rule.to_rulestring(), STEPS));
+    group.sample_size(10);
+    for sim in sims.iter_mut() {
+        let name = sim.0.clone();
+        group.bench_function(name, |b| b.iter(||
+            cells::benchmark(std::slice::from_mut(sim), &rule, bounds, SEED, STEPS, &tasks)));
+    }
+    group.finish();
+}
+
+// a 64^3 grid where every cell is full. they all die in the next step, so
+// it's almost only neighbor updates, across every chunk border.
+fn full_sim(rule: &Rule, moore_fast_path: bool) -> LeddooAtomic {
+    let mut sim = LeddooAtomic::new();
+    sim.moore_fast_path = moore_fast_path;
+    sim.set_bounds(IVec3::splat(64));
+    sim.spawn_cube(rule, 64);
+    sim
+}
+
+// that's where looking up the neighbor offsets per cell used to cost the most.
+fn full_grid(c: &mut Criterion) {
+    let tasks = TaskPool::new();
+
+    let mut group = c.benchmark_group("full grid step");
+    group.sample_size(10);
+    for rulestring in ["B4/S4/5/M", "B4/S4/5/M2"] {
+        let rule = Rule::from_rulestring(rulestring).unwrap();
+        group.bench_function(rulestring, |b| b.iter_batched(|| full_sim(&rule, true),
+            |mut sim| sim.update(&rule, &tasks), BatchSize::LargeInput));
+    }
+    group.finish();
+}
+
+// the same step with the unrolled moore r1 path on & off. run with
+// `--features moore_fast_path`, without it both are the scalar path.
+fn moore_fast_path(c: &mut Criterion) {
+    let tasks = TaskPool::new();
+    let rule = Rule::from_rulestring("B4/S4/5/M").unwrap();
+
+    let mut group = c.benchmark_group(
+        format!("full grid moore r1 (moore_fast_path: {})", cfg!(feature = "moore_fast_path")));
+    group.sample_size(10);
+    for (name, fast_path) in [("scalar", false), ("unrolled", true)] {
+        group.bench_function(name, |b| b.iter_batched(|| full_sim(&rule, fast_path),
+            |mut sim| sim.update(&rule, &tasks), BatchSize::LargeInput));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, backends, full_grid, moore_fast_path);
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+// `cargo bench --bench batch_size`: steps leddoo's atomic sim with different
+// batch sizes and prints where the time goes, to tune batch_size against the
+// core count. there are no statistics, it only prints the averages.
+use bevy::{math::IVec3, tasks::TaskPool};
+use celluar_automata::{
+    cells::leddoo::{LeddooAtomic, StepTimings},
+    rule::Rule,
+};
+
+const STEPS: u32 = 50;
+
+fn main() {
+    let rule = Rule::from_rulestring("B4/S4/5/M").unwrap();
+    let tasks = TaskPool::new();
+
+    // 4^3 & 8^3 chunks of 32^3. a full grid dies in the first step, so most
+    // of that one is neighbor updates.
+    for (name, bounds, full) in [("noise 128", 128, false), ("noise 256", 256, false), ("full 128", 128, true)] {
+        println!("{}:", name);
+        for batch_size in [1, 2, 4, 8, 16, 32, 64] {
+            let mut sim = LeddooAtomic::new();
+            sim.batch_size = batch_size;
+            sim.set_bounds(IVec3::splat(bounds));
+            if full { sim.spawn_cube(&rule, bounds); }
+            else    { sim.spawn_noise(&rule, Some(42)); }
+
+            let mut total = StepTimings::default();
+            for _ in 0..STEPS {
+                let timings = sim.update(&rule, &tasks);
+                total.values    += timings.values;
+                total.neighbors += timings.neighbors;
+            }
+            println!("{:>6} chunks/task: values {:8.3} ms, neighbors {:8.3} ms per step",
+                batch_size,
+                total.values.as_secs_f64() * 1000.0 / STEPS as f64,
+                total.neighbors.as_secs_f64() * 1000.0 / STEPS as f64);
+        }
+    }
+}
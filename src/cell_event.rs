@@ -1 +1,31 @@
+use bevy::math::IVec3;
+
 pub struct CellStatesChangedEvent;
+
+// not a bevy event: a sim sends these over a channel to whoever listens,
+// eg: for audio. see LeddooAtomic::set_event_sender.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CellEvent {
+    // entered the full state.
+    Born(IVec3),
+    // left the full state, so this is the start of the decay.
+    Died(IVec3),
+}
+
+// sent when the live cells / total cells ratio crosses Sims' fill threshold.
+// rules that fill the grid run into the boundary, which usually means the run
+// isn't interesting anymore.
+#[allow(dead_code)]
+pub struct GridFilledEvent {
+    pub fill_ratio: f32,
+}
+
+// a request to save the current frame as `path` (frame_<generation>.png).
+// sent on F12 and every `screenshot_interval` generations.
+// this bevy revision can't read the framebuffer back, so the frame is drawn
+// from the cell instances instead, see RecordSession::screenshot.
+pub struct ScreenshotEvent {
+    pub path: String,
+    pub generation: u64,
+}
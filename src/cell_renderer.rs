@@ -33,11 +33,25 @@ impl ExtractComponent for InstanceMaterialData {
     }
 }
 
+// marks the instance entity whose cells are alpha blended. it's drawn after
+// the opaque one, and its instances need to be sorted back to front.
+#[derive(Component, Clone, Copy)]
+pub struct Translucent;
+impl ExtractComponent for Translucent {
+    type Query = &'static Translucent;
+    type Filter = ();
+
+    fn extract_component(_item: bevy::ecs::query::QueryItem<Self::Query>) -> Self {
+        Translucent
+    }
+}
+
 pub struct CellMaterialPlugin;
 
 impl Plugin for CellMaterialPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(ExtractComponentPlugin::<InstanceMaterialData>::default());
+        app.add_plugin(ExtractComponentPlugin::<Translucent>::default());
         app.sub_app_mut(RenderApp)
             .add_render_command::<Transparent3d, DrawCustom>()
             .init_resource::<CellPipeline>()
@@ -64,10 +78,10 @@ fn queue_custom(
     mut pipeline_cache: ResMut<RenderPipelineCache>,
     meshes: Res<RenderAssets<Mesh>>,
     material_meshes: Query<
-        (Entity, &MeshUniform, &Handle<Mesh>),
-        (With<Handle<Mesh>>, With<InstanceMaterialData>),
+        (Entity, &Handle<Mesh>, Option<&Translucent>),
+        (With<Handle<Mesh>>, With<MeshUniform>, With<InstanceMaterialData>),
     >,
-    mut views: Query<(&ExtractedView, &mut RenderPhase<Transparent3d>)>,
+    mut views: Query<&mut RenderPhase<Transparent3d>, With<ExtractedView>>,
 ) {
     let draw_custom = transparent_3d_draw_functions
         .read()
@@ -76,13 +90,22 @@ fn queue_custom(
 
     let msaa_key = MeshPipelineKey::from_msaa_samples(msaa.samples);
 
-    for (view, mut transparent_phase) in views.iter_mut() {
-        let view_matrix = view.transform.compute_matrix();
-        let view_row_2 = view_matrix.row(2);
-        for (entity, mesh_uniform, mesh_handle) in material_meshes.iter() {
+    for mut transparent_phase in views.iter_mut() {
+        for (entity, mesh_handle, translucent) in material_meshes.iter() {
             if let Some(mesh) = meshes.get(mesh_handle) {
-                let key =
+                let mut key =
                     msaa_key | MeshPipelineKey::from_primitive_topology(mesh.primitive_topology);
+                // both entities are at the origin, so the distance only orders
+                // them: the phase draws the smallest first, opaque cells go
+                // first, so the blended ones are drawn over them.
+                // TRANSPARENT_MAIN_PASS turns on blending & turns off depth
+                // writes.
+                let distance =
+                    if translucent.is_some() {
+                        key |= MeshPipelineKey::TRANSPARENT_MAIN_PASS;
+                        f32::MAX
+                    }
+                    else { f32::MIN };
                 let pipeline = pipelines
                     .specialize(&mut pipeline_cache, &custom_pipeline, key, &mesh.layout)
                     .unwrap();
@@ -90,7 +113,7 @@ fn queue_custom(
                     entity,
                     pipeline,
                     draw_function: draw_custom,
-                    distance: view_row_2.dot(mesh_uniform.transform.col(3)),
+                    distance,
                 });
             }
         }
@@ -229,29 +252,38 @@ impl EntityRenderCommand for DrawMeshInstanced {
 
 
 pub struct CellRenderer {
-    pub bounds: i32,
+    pub bounds: IVec3,
     pub values: Vec<u8>,
-    pub neighbors: Vec<u8>,
+    pub neighbors: Vec<u16>,
+    // optional per cell colors, overriding the color method. emptied before
+    // every render, sims that use it need to resize it.
+    pub colors: Vec<Option<Color>>,
+    // generations since each cell was born, for ColorMethod::BirthTime.
+    // emptied before every render like colors, sims that track births
+    // resize it.
+    pub ages: Vec<u32>,
 }
 
 impl CellRenderer {
     pub fn new() -> CellRenderer {
         CellRenderer {
-            bounds: 0,
+            bounds: IVec3::ZERO,
             values: vec![],
             neighbors: vec![],
+            colors: vec![],
+            ages: vec![],
         }
     }
 
     pub fn cell_count(&self) -> usize {
-        (self.bounds*self.bounds*self.bounds) as usize
+        utils::volume(self.bounds)
     }
 
-    pub fn set_bounds(&mut self, new_bounds: i32) {
+    pub fn set_bounds(&mut self, new_bounds: IVec3) {
         if new_bounds != self.bounds {
-            let new_count = new_bounds*new_bounds*new_bounds;
-            self.values.resize(new_count as usize, 0);
-            self.neighbors.resize(new_count as usize, 0);
+            let new_count = utils::volume(new_bounds);
+            self.values.resize(new_count, 0);
+            self.neighbors.resize(new_count, 0);
             self.bounds = new_bounds;
         }
     }
@@ -263,12 +295,12 @@ impl CellRenderer {
         self.neighbors.resize(self.cell_count(), 0);
     }
 
-    pub fn set(&mut self, index: usize, value: u8, neighbors: u8) {
+    pub fn set(&mut self, index: usize, value: u8, neighbors: u16) {
         self.values[index]    = value;
         self.neighbors[index] = neighbors;
     }
 
-    pub fn set_pos(&mut self, pos: IVec3, value: u8, neighbors: u8) {
+    pub fn set_pos(&mut self, pos: IVec3, value: u8, neighbors: u16) {
         self.set(utils::pos_to_index(pos, self.bounds), value, neighbors);
     }
 }
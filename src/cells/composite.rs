@@ -0,0 +1,240 @@
+/*
+    how it works:
+        - the grid is split into regions, each with its own rule. a cell is
+          updated with the rule of the first region that contains it (so
+          earlier regions win where they overlap). cells outside all regions
+          are always dead.
+        - a neighbor counts if it's in the full state of *its own* rule. so
+          cells at a seam see the other side's cells, which is the point.
+        - neighbor counts are recounted from scratch every update, as each
+          cell counts with its own neighbourhood. that's slow, this is for
+          demos, not for big grids.
+        - the rule passed to update is ignored.
+*/
+
+use bevy::{
+    math::{IVec3, Vec3},
+    prelude::Color,
+    tasks::TaskPool,
+};
+
+use crate::{
+    cell_renderer::{CellRenderer},
+    rule::{ColorMethod, Rule},
+    utils,
+};
+
+use std::time::{Duration, Instant};
+
+
+// a box relative to the bounds, so (0, 0, 0)..(0.5, 1, 1) is the left half,
+// whatever the bounds are. min is inclusive, max exclusive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingRegion {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl BoundingRegion {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        BoundingRegion { min, max }
+    }
+
+    // uses the cell's center, so neighboring regions never share a cell.
+    pub fn contains(&self, pos: IVec3, bounds: IVec3) -> bool {
+        let pos = (pos.as_vec3() + Vec3::splat(0.5)) / bounds.max(IVec3::ONE).as_vec3();
+        pos.cmpge(self.min).all() && pos.cmplt(self.max).all()
+    }
+}
+
+
+pub struct CompositeSim {
+    regions: Vec<(BoundingRegion, Rule)>,
+    // per region, None uses the ui's color method.
+    colors: Vec<Option<(ColorMethod, Color, Color)>>,
+    values: Vec<u8>,
+    neighbors: Vec<u16>,
+    // index of the owning region per cell.
+    owners: Vec<Option<usize>>,
+    bounds: IVec3,
+    generation: u64,
+    // wall clock time of the last update.
+    last_step: Duration,
+}
+
+impl CompositeSim {
+    // the regions' rules have to be ones this sim can run, see supports.
+    pub fn new(regions: Vec<(BoundingRegion, Rule)>) -> Self {
+        for (_, rule) in regions.iter() {
+            assert!(Self::supports(rule), "the composite sim can't run {}", rule.to_rulestring());
+        }
+        CompositeSim {
+            colors: vec![None; regions.len()],
+            regions,
+            values: vec![],
+            neighbors: vec![],
+            owners: vec![],
+            bounds: IVec3::ZERO,
+            generation: 0,
+            last_step: Duration::ZERO,
+        }
+    }
+
+    // survival counts with neighbour_method, there's only one counter. no
+    // margolus block updates or symmetry pass either.
+    pub fn supports(rule: &Rule) -> bool {
+        rule.with_survival_neighbours().is_none() && rule.margolus.is_none()
+            && rule.enforce_symmetry.is_none()
+    }
+
+    pub fn set_region_color(&mut self, region: usize, method: ColorMethod, color1: Color, color2: Color) {
+        self.colors[region] = Some((method, color1, color2));
+    }
+
+    pub fn set_bounds(&mut self, new_bounds: IVec3) -> IVec3 {
+        let new_bounds = utils::clamp_bounds_quiet(new_bounds);
+        if new_bounds != self.bounds {
+            let volume = utils::volume(new_bounds);
+            self.values    = vec![0; volume];
+            self.neighbors = vec![0; volume];
+            self.bounds = new_bounds;
+            self.generation = 0;
+
+            let regions = &self.regions;
+            self.owners = (0..volume)
+                .map(|index| {
+                    let pos = utils::index_to_pos(index, new_bounds);
+                    regions.iter().position(|(region, _)| region.contains(pos, new_bounds))
+                })
+                .collect();
+        }
+        self.bounds
+    }
+
+    fn is_full(&self, index: usize) -> bool {
+        match self.owners[index] {
+            Some(owner) => self.values[index] == self.regions[owner].1.states,
+            None => false,
+        }
+    }
+
+    fn count_neighbors(&self, index: usize, rule: &Rule) -> u16 {
+        let pos = utils::index_to_pos(index, self.bounds);
+        let mut count = 0;
+        for (dir, weight) in rule.neighbours() {
+            if let Some(neighbor_pos) = rule.boundary_mode.resolve(pos + dir, self.bounds) {
+                if self.is_full(utils::pos_to_index(neighbor_pos, self.bounds)) {
+                    count += weight;
+                }
+            }
+        }
+        count
+    }
+
+    pub fn update(&mut self) {
+        for index in 0..self.values.len() {
+            self.neighbors[index] = match self.owners[index] {
+                Some(owner) => self.count_neighbors(index, &self.regions[owner].1),
+                None => 0,
+            };
+        }
+
+        for index in 0..self.values.len() {
+            let rule = match self.owners[index] {
+                Some(owner) => &self.regions[owner].1,
+                None => continue,
+            };
+            let value     = &mut self.values[index];
+            let neighbors = self.neighbors[index];
+
+            if let Some(transitions) = &rule.transitions {
+                *value = transitions.next(*value, neighbors);
+            }
+            else if *value == 0 {
+                if rule.birth_rule.in_range(neighbors) {
+                    *value = rule.states;
+                }
+            }
+            else if *value < rule.states || !rule.survival_rule.in_range(neighbors) {
+                *value = rule.death_mode.next(*value);
+            }
+        }
+
+        self.generation += 1;
+    }
+
+    // full state of each cell's own rule. cells outside all regions stay dead.
+    pub fn spawn_noise(&mut self, density: f32, seed: Option<u64>) {
+        let bounds = self.bounds;
+        utils::make_some_noise_default(utils::center(bounds), density, seed, |pos| {
+            let pos = utils::wrap(pos, bounds);
+            let index = utils::pos_to_index(pos, bounds);
+            if let Some(owner) = self.owners[index] {
+                self.values[index] = self.regions[owner].1.states;
+            }
+        });
+    }
+}
+
+
+impl crate::cells::Sim for CompositeSim {
+    fn update(&mut self, _rule: &Rule, _task_pool: &TaskPool) {
+        let t0 = Instant::now();
+        self.update();
+        self.last_step = t0.elapsed();
+    }
+
+    fn last_step_millis(&self) -> f32 {
+        self.last_step.as_secs_f32() * 1000.0
+    }
+
+    fn render(&self, renderer: &mut CellRenderer) {
+        let bounds = self.bounds;
+        renderer.colors.resize(renderer.cell_count(), None);
+        for index in 0..self.values.len() {
+            let value     = self.values[index];
+            let neighbors = self.neighbors[index];
+            renderer.set(index, value, neighbors);
+
+            let owner = match self.owners[index] {
+                Some(owner) if value != 0 => owner,
+                _ => continue,
+            };
+            if let Some((method, color1, color2)) = &self.colors[owner] {
+                let pos = utils::index_to_pos(index, bounds);
+                renderer.colors[index] = Some(method.color(
+                    *color1, *color2,
+                    &self.regions[owner].1,
+                    value, neighbors,
+                    self.regions[owner].1.boundary_mode.dist_to_center(pos, bounds),
+                    pos.as_vec3() / (bounds - IVec3::ONE).max(IVec3::ONE).as_vec3(),
+                    if method.needs_gradient() {
+                        self.regions[owner].1.boundary_mode.density_gradient(pos, bounds, |index| self.values[index] != 0)
+                    }
+                    else { Vec3::ZERO },
+                    0));
+            }
+        }
+    }
+
+    // only the density is taken from the ui's rule.
+    fn spawn_noise(&mut self, rule: &Rule, seed: Option<u64>) {
+        self.spawn_noise(rule.noise_density, seed);
+    }
+
+    fn cell_count(&self) -> usize {
+        self.values.iter().filter(|value| **value != 0).count()
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn bounds(&self) -> IVec3 {
+        self.bounds
+    }
+
+    fn set_bounds(&mut self, new_bounds: IVec3) -> IVec3 {
+        self.set_bounds(new_bounds)
+    }
+}
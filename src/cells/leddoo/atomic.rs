@@ -7,6 +7,9 @@
               grouped logically for parallelism.
         - each chunk is processed by one task.
         - updating the cells is entirely lock free and atomic free.
+            - the cells are stored as atomics, but only read & written with
+              relaxed loads & stores, which are plain loads & stores on the
+              cpu. that keeps the sharing between tasks safe rust.
             - this is equivalent to spawning one task per cell.
             - each task collects a list of global cell indices that changed in
               the respective chunk.
@@ -45,65 +48,151 @@
           given by the tight loop.
         - there appears to be some measurable amount of overhead: increasing the
           bounding size improves the mt speedup ratio.
+        - chunks where nothing happened are skipped: a cell's next value only
+          depends on its value & neighbor count, so if no value in a chunk
+          changed in the last update and no neighbor count changed since,
+          the next update can't change anything either. the value phase marks
+          the chunks with changed values dirty, the neighbor phase the chunks
+          a spawn or death reaches into. anything else that sets cells (and
+          a different rule) marks all chunks dirty.
+        - with the `moore_fast_path` feature, interior neighbor updates for
+          moore r1 skip the offset list and the position math: the 26
+          neighbors are 9 rows of 3 consecutive indices. both paths are in
+          `cargo bench --features moore_fast_path --bench backends -- moore`.
+        - all atomics are relaxed: the phases are separated by joining the
+          tasks, which already orders everything. the `seq_cst_atomics`
+          feature makes them all SeqCst, to rule out ordering issues when
+          chasing wrong neighbor counts.
+        - rules with a margolus table don't use any of the above, see
+          margolus_blocks. the neighbor counts are still kept up to date, so
+          switching back to a totalistic rule just works.
+        - rules with a separate survival neighbourhood keep a second set of
+          counts, survival_neighbors, updated along with the first. that's
+          another u16 per cell, so 5 bytes per cell instead of 3: 32 MB more
+          at MAX_BOUNDS. it's only allocated while the rule has one, see
+          neighbor_offsets.
 */
 
 use bevy::{
-    math::{ivec3, IVec3},
+    math::{ivec3, vec3, BVec3, IVec3, Vec3},
+    prelude::Color,
     tasks::{TaskPool},
 };
 
 use futures_lite::future;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use crate::{
+    cell_event::CellEvent,
     cell_renderer::{CellRenderer},
-    rule::Rule,
+    cells::{self, NeighbourMismatch},
+    neighbours::NeighbourMethod,
+    rule::{BoundaryMode, MargolusTable, Rule, Symmetry},
+    stats::StatsRecorder,
     utils::{self},
 };
 
-use std::sync::{atomic::{AtomicU8, Ordering}, Arc};
-use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU16, AtomicU32, Ordering};
+use std::sync::mpsc::{SyncSender, TrySendError};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
 
 
-const CHUNK_SIZE:       usize = 32;
-const CHUNK_CELL_COUNT: usize = CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE;
+// see how it works.
+const ORDERING: Ordering =
+    if cfg!(feature = "seq_cst_atomics") { Ordering::SeqCst }
+    else { Ordering::Relaxed };
 
-fn bounds_to_chunk_radius(bounds: i32) -> usize {
-    (bounds as usize + CHUNK_SIZE - 1) / CHUNK_SIZE
+const SAVE_MAGIC: &[u8; 4] = b"CA3D";
+const WALL_COLOR: Color = Color::GRAY;
+
+// corner `i` of a unit cube is at ((i>>0)&1, (i>>1)&1, (i>>2)&1) - 0.5.
+// faces are counter clockwise when viewed from outside.
+const CUBE_FACES: [[usize; 4]; 6] = [
+    [0, 4, 6, 2], [1, 3, 7, 5],
+    [0, 1, 5, 4], [2, 6, 7, 3],
+    [0, 2, 3, 1], [4, 5, 7, 6],
+];
+
+// fill ratio at which run_until calls a grid filled. same as the ui's
+// default fill threshold.
+const RUN_FILLED_RATIO: f32 = 0.9;
+
+// how many generations of state hashes to keep. detects oscillators with a
+// period of up to STABLE_HISTORY - 1.
+const STABLE_HISTORY: usize = 4;
+
+// chunk edge length. the sim is generic over it (LeddooAtomicChunked<N>), so
+// different sizes can be benchmarked side by side.
+pub const DEFAULT_CHUNK_SIZE: usize = 32;
+
+// chunks per axis, rounded up.
+fn bounds_to_chunk_counts<const N: usize>(bounds: IVec3) -> IVec3 {
+    let size = N as i32;
+    (bounds.max(IVec3::ZERO) + IVec3::splat(size - 1)) / size
 }
 
-fn chunk_offset_to_pos(offset: usize) -> IVec3 {
-    utils::index_to_pos(offset, CHUNK_SIZE as i32)
+fn chunk_offset_to_pos<const N: usize>(offset: usize) -> IVec3 {
+    utils::index_to_pos(offset, IVec3::splat(N as i32))
 }
 
-fn chunk_is_border_pos(pos: IVec3, offset: i32) -> bool {
-    pos.x - offset <= 0 || pos.x + offset >= CHUNK_SIZE as i32 - 1 ||
-    pos.y - offset <= 0 || pos.y + offset >= CHUNK_SIZE as i32 - 1 ||
-    pos.z - offset <= 0 || pos.z + offset >= CHUNK_SIZE as i32 - 1
+// the rule's neighbor offsets & weights. computed once per update (or spawn)
+// instead of once per changed cell.
+struct Offsets {
+    birth: Vec<(IVec3, u16)>,
+    // None if survival counts with the birth counts.
+    survival: Option<(NeighbourMethod, Vec<(IVec3, u16)>)>,
+    // see LeddooAtomicChunked::moore_fast_path.
+    moore_fast_path: bool,
 }
 
+fn chunk_is_border_pos<const N: usize>(pos: IVec3, offset: i32) -> bool {
+    pos.x - offset <= 0 || pos.x + offset >= N as i32 - 1 ||
+    pos.y - offset <= 0 || pos.y + offset >= N as i32 - 1 ||
+    pos.z - offset <= 0 || pos.z + offset >= N as i32 - 1
+}
 
 
-#[derive(Clone)]
-struct Values (Arc<Vec<UnsafeCell<AtomicU8>>>);
 
-unsafe impl Sync for Values {}
-unsafe impl Send for Values {}
+// the tasks borrow these (see update_async), so no locks or arcs. each cell
+// is only written by the task of its chunk during the value phase.
+struct Values (Vec<AtomicU8>);
 
 impl Values {
     fn new(length: usize) -> Values {
-        Values(Arc::new((0..length).map(|_| UnsafeCell::new(AtomicU8::new(0))).collect()))
+        Values((0..length).map(|_| AtomicU8::new(0)).collect())
     }
 
     fn read(&self, index: usize) -> u8 {
-        unsafe { *(*self.0[index].get()).get_mut() }
+        self.0[index].load(ORDERING)
+    }
+
+    fn set(&self, index: usize, value: u8) {
+        self.0[index].store(value, ORDERING)
+    }
+}
+
+
+// like Values, but u16, as neighbor counts can exceed 255 for larger radii.
+// also used with read-modify-write atomics, see update_neighbors.
+struct Neighbors (Vec<AtomicU16>);
+
+impl Neighbors {
+    fn new(length: usize) -> Neighbors {
+        Neighbors((0..length).map(|_| AtomicU16::new(0)).collect())
+    }
+
+    fn read(&self, index: usize) -> u16 {
+        self.0[index].load(ORDERING)
     }
 
-    fn write(&self, index: usize) -> &mut u8 {
-        unsafe { (*self.0[index].get()).get_mut() }
+    fn set(&self, index: usize, value: u16) {
+        self.0[index].store(value, ORDERING)
     }
 
-    fn atomic(&self,index: usize) -> &mut AtomicU8 {
-        unsafe { &mut *self.0[index].get() }
+    fn atomic(&self, index: usize) -> &AtomicU16 {
+        &self.0[index]
     }
 }
 
@@ -112,48 +201,303 @@ fn cell_is_dead(value: u8) -> bool {
     value == 0
 }
 
+// hashes of all live cells are summed up, so the grid hash doesn't depend on
+// the order in which the chunks are processed.
+fn cell_hash(index: usize, value: u8) -> u64 {
+    // splitmix64 finalizer.
+    let mut x = ((index as u64) << 8) | value as u64;
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+
+// time spent in the two phases of an update, see benches/batch_size.rs.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StepTimings {
+    pub values:    Duration,
+    pub neighbors: Duration,
+}
+
+
+// how run_until ended, with the generation it ended at.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunOutcome {
+    Extinct(u64),
+    // generation & period.
+    Stable(u64, u64),
+    Filled(u64),
+    MaxReached,
+}
+
+// see export_ply.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+// the live cells before an update, as (start index, length, value) runs like
+// the save format. the rule is kept to rebuild the neighbor counts.
+struct Snapshot {
+    runs: Vec<(u32, u32, u8)>,
+    rule: Rule,
+}
+
+
+pub type LeddooAtomic = LeddooAtomicChunked<DEFAULT_CHUNK_SIZE>;
 
-pub struct LeddooAtomic {
+pub struct LeddooAtomicChunked<const N: usize> {
+    // chunks per task. small chunks (or few cells per chunk) spend most of
+    // their time in task overhead, batching them helps.
+    pub batch_size: usize,
+    // at most this many tasks per phase, however many threads the pool has.
+    // the chunks are batched more to fit. None uses one task per batch.
+    pub max_tasks: Option<usize>,
+    // use the unrolled moore r1 path. only has an effect with the
+    // `moore_fast_path` feature, the bench turns it off to compare both.
+    pub moore_fast_path: bool,
     values:    Values,
-    neighbors: Values,
-    chunk_radius: usize,
+    neighbors: Neighbors,
+    // the counts of the survival neighbourhood, if the rule has a separate
+    // one. empty otherwise.
+    survival_neighbors: Neighbors,
+    // the rule survival_neighbors were counted with.
+    survival_rule: Option<Rule>,
+    // the live runs (see live_runs) after the last spawn before the first
+    // update, and the bounds they're for. kept through resets, see
+    // reset_to_initial.
+    initial: Option<(IVec3, Vec<(u32, u32, u8)>)>,
+    // the generation each cell last became full in, see set_track_births.
+    // empty unless track_births, it's 4 more bytes per cell.
+    born: Vec<AtomicU32>,
+    track_births: bool,
+    chunk_counts: IVec3,
     chunk_count:  usize,
+    generation:   u64,
+    // hashes of the live cells after the last few updates, newest last.
+    state_hashes: VecDeque<u64>,
+    stats: StatsRecorder,
+    // generations step_back can undo. 0 (the default) disables the history,
+    // which saves a pass over the grid & a copy of the live cells per update.
+    history_length: usize,
+    history: VecDeque<Snapshot>,
+    // cells that entered/left the full state in the last update.
+    last_births: usize,
+    last_deaths: usize,
+    // cells that are never updated, but count as full neighbors, see
+    // set_wall. empty until the first wall is set.
+    walls: Vec<bool>,
+    // true if there are no live cells for sure. only true after an update
+    // that left nothing or a fresh grid, anything that sets cells clears it.
+    empty: bool,
+    // per chunk, see "chunks where nothing happened" above. set by the tasks,
+    // read & cleared at the start of an update.
+    dirty: Vec<AtomicBool>,
+    // (hash, live) of each chunk after its last update, clean chunks reuse it.
+    chunk_summaries: Vec<(u64, usize)>,
+    // rule of the last update.
+    last_rule: Option<Rule>,
+    // both phases of the last update, without the yield in between.
+    last_step: Duration,
+    // gets the births & deaths of each update as one batch, see
+    // set_event_sender.
+    event_sender: Option<SyncSender<Vec<CellEvent>>>,
 }
 
-impl LeddooAtomic {
+impl<const N: usize> LeddooAtomicChunked<N> {
     pub fn new() -> Self {
-        LeddooAtomic {
+        LeddooAtomicChunked {
+            batch_size: 1,
+            max_tasks: None,
+            moore_fast_path: true,
             values:    Values::new(0),
-            neighbors: Values::new(0),
-            chunk_radius: 0,
+            neighbors: Neighbors::new(0),
+            survival_neighbors: Neighbors::new(0),
+            survival_rule: None,
+            initial: None,
+            born: vec![],
+            track_births: false,
+            chunk_counts: IVec3::ZERO,
             chunk_count: 0,
+            generation: 0,
+            state_hashes: VecDeque::new(),
+            stats: StatsRecorder::new(),
+            history_length: 0,
+            history: VecDeque::new(),
+            last_births: 0,
+            last_deaths: 0,
+            walls: vec![],
+            empty: true,
+            dirty: vec![],
+            chunk_summaries: vec![],
+            last_rule: None,
+            last_step: Duration::ZERO,
+            event_sender: None,
         }
     }
 
-    pub fn set_bounds(&mut self, new_bounds: i32) -> i32 {
-        let radius = bounds_to_chunk_radius(new_bounds);
-        let bounds = radius * CHUNK_SIZE;
-        self.values    = Values::new(bounds*bounds*bounds);
-        self.neighbors = Values::new(bounds*bounds*bounds);
-        self.chunk_radius = radius;
-        self.chunk_count  = radius*radius*radius;
-        bounds as i32
+    pub fn set_bounds(&mut self, new_bounds: IVec3) -> IVec3 {
+        // rounded up to whole chunks after clamping, so this can still be a
+        // bit over MAX_BOUNDS. with an even N, that also makes them even,
+        // which margolus_blocks needs.
+        let counts = bounds_to_chunk_counts::<N>(utils::clamp_bounds_quiet(new_bounds));
+        let bounds = counts * N as i32;
+        self.values    = Values::new(utils::volume(bounds));
+        self.neighbors = Neighbors::new(utils::volume(bounds));
+        self.survival_neighbors = Neighbors::new(0);
+        self.survival_rule = None;
+        self.born =
+            if self.track_births { (0..utils::volume(bounds)).map(|_| AtomicU32::new(0)).collect() }
+            else { vec![] };
+        self.chunk_counts = counts;
+        self.chunk_count  = utils::volume(counts);
+        // reset goes through here too.
+        self.generation   = 0;
+        self.state_hashes.clear();
+        self.stats.clear();
+        self.history.clear();
+        self.last_births = 0;
+        self.last_deaths = 0;
+        self.walls.clear();
+        self.empty = true;
+        self.dirty = (0..self.chunk_count).map(|_| AtomicBool::new(true)).collect();
+        self.chunk_summaries = vec![(0, 0); self.chunk_count];
+        self.last_rule = None;
+        bounds
     }
 
-    pub fn bounds(&self) -> i32 {
-        (self.chunk_radius * CHUNK_SIZE) as i32
+    // every update sends one batch with its births & deaths, unless there
+    // were none. the channel's bound is the throttle: while it's full,
+    // batches are dropped instead of blocking the sim. eg:
+    //     let (sender, receiver) = std::sync::mpsc::sync_channel(4);
+    // the sender is dropped once the receiver is gone.
+    #[allow(dead_code)]
+    pub fn set_event_sender(&mut self, sender: Option<SyncSender<Vec<CellEvent>>>) {
+        self.event_sender = sender;
+    }
+
+    // the history starts with the next update, and drops the oldest
+    // generations if it's shortened.
+    pub fn set_history_length(&mut self, length: usize) {
+        self.history_length = length;
+        while self.history.len() > length {
+            self.history.pop_front();
+        }
+    }
+
+    // for ColorMethod::BirthTime. cells that are already live when this
+    // is turned on count as born now.
+    pub fn set_track_births(&mut self, track: bool) {
+        if track == self.track_births {
+            return;
+        }
+        self.track_births = track;
+        let generation = self.generation as u32;
+        self.born =
+            if track { (0..self.total_cell_count()).map(|_| AtomicU32::new(generation)).collect() }
+            else { vec![] };
+    }
+
+    // generations since the cell at `index` was born. None without
+    // set_track_births.
+    #[allow(dead_code)]
+    pub fn age(&self, index: usize) -> Option<u32> {
+        let born = self.born.get(index)?.load(ORDERING);
+        Some((self.generation as u32).wrapping_sub(born))
+    }
+
+    // no-op unless births are tracked.
+    fn mark_born(&self, index: usize, generation: u64) {
+        if let Some(born) = self.born.get(index) {
+            born.store(generation as u32, ORDERING);
+        }
+    }
+
+    // the offsets for update_neighbors. also (re)counts survival_neighbors
+    // from the current values if the rule's survival neighbourhood changed,
+    // so call this before changing any values.
+    fn neighbor_offsets(&mut self, rule: &Rule) -> Offsets {
+        let survival_rule = rule.with_survival_neighbours();
+        if survival_rule != self.survival_rule {
+            self.survival_neighbors = match &survival_rule {
+                Some(survival_rule) => {
+                    let counts = cells::count_neighbors(survival_rule, self.bounds(),
+                        |index| self.values.read(index) == rule.states);
+                    Neighbors(counts.into_iter().map(AtomicU16::new).collect())
+                }
+                None => Neighbors::new(0),
+            };
+            self.survival_rule = survival_rule;
+        }
+
+        Offsets {
+            birth: rule.neighbours().collect(),
+            survival: self.survival_rule.as_ref()
+                .map(|survival_rule| (survival_rule.neighbour_method, survival_rule.neighbours().collect())),
+            moore_fast_path: self.moore_fast_path,
+        }
+    }
+
+    fn mark_all_dirty(&mut self) {
+        for dirty in self.dirty.iter_mut() {
+            *dirty.get_mut() = true;
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn stats(&self) -> &StatsRecorder {
+        &self.stats
+    }
+
+    pub fn bounds(&self) -> IVec3 {
+        self.chunk_counts * N as i32
+    }
+
+    // like set_bounds, but keeps the cells at their positions. cells outside
+    // the new bounds are dropped. the generation & stats keep going.
+    pub fn resize_preserving(&mut self, rule: &Rule, new_bounds: IVec3) -> IVec3 {
+        let cells: Vec<(IVec3, u8)> = self.live_cells().collect();
+
+        let generation = self.generation;
+        let stats = std::mem::replace(&mut self.stats, StatsRecorder::new());
+        let bounds = self.set_bounds(new_bounds);
+        self.generation = generation;
+        self.stats = stats;
+
+        let offsets = self.neighbor_offsets(rule);
+        for (pos, value) in cells {
+            if !utils::is_in_bounds(pos, bounds) {
+                continue;
+            }
+            let index = utils::pos_to_index(pos, bounds);
+            self.values.set(index, value);
+            self.empty = false;
+            if value == rule.states {
+                Self::update_neighbors(&self.neighbors, &self.survival_neighbors, index, bounds, rule, &offsets, true);
+            }
+        }
+        bounds
     }
 
     pub fn total_cell_count(&self) -> usize {
-        self.chunk_count * CHUNK_CELL_COUNT
+        self.chunk_count * N*N*N
     }
 
     pub fn center(&self) -> IVec3 {
-        let center = self.bounds() / 2;
-        ivec3(center, center, center)
+        utils::center(self.bounds())
+    }
+
+    // no scan if the last update (or reset) left nothing.
+    pub fn is_empty(&self) -> bool {
+        self.empty || self.live_cells().next().is_none()
     }
 
     pub fn cell_count(&self) -> usize {
+        if self.empty {
+            return 0;
+        }
         let mut result = 0;
         for index in 0..self.total_cell_count() {
             if !cell_is_dead(self.values.read(index)) {
@@ -163,206 +507,1783 @@ impl LeddooAtomic {
         result
     }
 
+    // cells where `f(value, neighbors)` holds, dead ones included. eg:
+    // full cells with exactly 4 neighbors. neighbors is a u16, like
+    // everywhere else, as radius 3 counts can exceed 255.
+    pub fn count_where<F: Fn(u8, u16) -> bool>(&self, f: F) -> usize {
+        let mut result = 0;
+        for index in 0..self.total_cell_count() {
+            if f(self.values.read(index), self.neighbors.read(index)) {
+                result += 1;
+            }
+        }
+        result
+    }
+
+    // positions & values of the live cells, in index order. no locks and
+    // nothing is collected: the iterator borrows the sim, so it can't be
+    // updated while iterating.
+    pub fn live_cells(&self) -> impl Iterator<Item = (IVec3, u8)> + '_ {
+        let bounds = self.bounds();
+        (0..self.total_cell_count())
+            .map(|index| (index, self.values.read(index)))
+            .filter(|(_, value)| !cell_is_dead(*value))
+            .map(move |(index, value)| (utils::index_to_pos(index, bounds), value))
+    }
+
+    // min & max position of the live cells, inclusive.
+    #[allow(dead_code)]
+    pub fn live_bounds(&self) -> Option<(IVec3, IVec3)> {
+        self.live_cells()
+            .map(|(pos, _)| pos)
+            .fold(None, |result, pos| match result {
+                Some((min, max)) => Some((pos.min(min), pos.max(max))),
+                None => Some((pos, pos)),
+            })
+    }
+
+    // number of cells per neighbor count, `histogram[count]`. the sim doesn't
+    // know the rule, so it goes up to the highest count seen (and is empty
+    // for an empty grid). only reads, so it can run on another thread
+    // between updates.
+    pub fn neighbour_histogram(&self) -> Vec<usize> {
+        let mut histogram = vec![];
+        for index in 0..self.total_cell_count() {
+            let count = self.neighbors.read(index) as usize;
+            if count >= histogram.len() {
+                histogram.resize(count + 1, 0);
+            }
+            histogram[count] += 1;
+        }
+        histogram
+    }
+
+    // number of live cells per value, `distribution[value - 1]`, so the last
+    // entry is the full state if any cell is in it. like the histogram, it
+    // only goes up to the highest value seen.
+    pub fn state_distribution(&self) -> Vec<usize> {
+        let mut distribution = vec![];
+        for (_, value) in self.live_cells() {
+            let value = value as usize;
+            if value > distribution.len() {
+                distribution.resize(value, 0);
+            }
+            distribution[value - 1] += 1;
+        }
+        distribution
+    }
+
+    pub fn fill_ratio(&self) -> f32 {
+        self.cell_count() as f32 / self.total_cell_count().max(1) as f32
+    }
+
+    // stable hash of the bounds & every cell's value, in index order. for
+    // checking that refactors don't change what a seeded run does.
+    // `with_neighbors` also hashes the neighbor counts.
+    #[allow(dead_code)]
+    pub fn state_hash(&self, with_neighbors: bool) -> u64 {
+        let bounds = self.bounds();
+        let bounds = [bounds.x, bounds.y, bounds.z].into_iter()
+            .flat_map(|extent| (extent as u32).to_le_bytes());
+        let values = (0..self.total_cell_count()).map(|index| self.values.read(index));
+        let neighbors = (0..self.total_cell_count())
+            .filter(|_| with_neighbors)
+            .flat_map(|index| self.neighbors.read(index).to_le_bytes());
+        utils::fnv1a(bounds.chain(values).chain(neighbors))
+    }
+
+    // number of groups of live cells that are connected through the rule's
+    // neighborhood (and boundary mode). walks the whole grid, so it's slow.
+    // only reads, so it can run in a task while nothing updates the sim.
+    pub fn component_count(&self, rule: &Rule) -> usize {
+        let bounds = self.bounds();
+        let mut visited = vec![false; self.total_cell_count()];
+        let mut stack = vec![];
+        let mut count = 0;
+
+        for start in 0..self.total_cell_count() {
+            if visited[start] || cell_is_dead(self.values.read(start)) {
+                continue;
+            }
+
+            count += 1;
+            visited[start] = true;
+            stack.push(start);
+            while let Some(index) = stack.pop() {
+                let pos = utils::index_to_pos(index, bounds);
+                // zero weights don't connect anything.
+                for (dir, _) in rule.neighbour_method.weighted_neighbours().filter(|(_, weight)| *weight > 0) {
+                    let neighbor_pos = match rule.boundary_mode.resolve(pos + dir, bounds) {
+                        Some(pos) => pos,
+                        None => continue,
+                    };
+                    let neighbor_index = utils::pos_to_index(neighbor_pos, bounds);
+                    if !visited[neighbor_index] && !cell_is_dead(self.values.read(neighbor_index)) {
+                        visited[neighbor_index] = true;
+                        stack.push(neighbor_index);
+                    }
+                }
+            }
+        }
+        count
+    }
+
+    // positions outside the bounds go through the rule's boundary mode, so
+    // they're `None` only if the mode drops them.
+    pub fn get_cell(&self, rule: &Rule, pos: IVec3) -> Option<u8> {
+        let pos = rule.boundary_mode.resolve(pos, self.bounds())?;
+        Some(self.values.read(utils::pos_to_index(pos, self.bounds())))
+    }
+
+    // handy to check the neighbor counts against `validate`.
+    pub fn neighbours_at(&self, rule: &Rule, pos: IVec3) -> Option<u16> {
+        let pos = rule.boundary_mode.resolve(pos, self.bounds())?;
+        Some(self.neighbors.read(utils::pos_to_index(pos, self.bounds())))
+    }
+
 
+    // both counts, survival_neighbors is only touched if the offsets have
+    // survival offsets.
     fn update_neighbors(
-        neighbors: &Values,
-        index: usize, bounds: i32,
-        rule: &Rule, inc: bool
+        neighbors: &Neighbors, survival_neighbors: &Neighbors,
+        index: usize, bounds: IVec3,
+        rule: &Rule, offsets: &Offsets, inc: bool
+    ) {
+        let fast_path = offsets.moore_fast_path;
+        Self::update_counts(neighbors, index, bounds, rule, rule.neighbour_method, &offsets.birth, fast_path, inc);
+        if let Some((method, survival_offsets)) = &offsets.survival {
+            Self::update_counts(survival_neighbors, index, bounds, rule, *method, survival_offsets, fast_path, inc);
+        }
+    }
+
+    // `offsets` are the ones of `method`.
+    #[allow(clippy::too_many_arguments)]
+    fn update_counts(
+        neighbors: &Neighbors,
+        index: usize, bounds: IVec3,
+        rule: &Rule, method: NeighbourMethod, offsets: &[(IVec3, u16)], fast_path: bool, inc: bool
     ) {
         let pos   = utils::index_to_pos(index, bounds);
-        let local = pos % CHUNK_SIZE as i32;
-        if chunk_is_border_pos(local, 1) {
-            for dir in rule.neighbour_method.get_neighbour_iter() {
-                let neighbor_pos = utils::wrap(pos + *dir, bounds);
+        let local = pos % N as i32;
+        // neighboring chunks can update the outer `radius` layers of this chunk.
+        // a cell needs atomics if it updates any of them, so if it's closer than
+        // 2*radius to the chunk border.
+        let radius = method.radius() as i32;
+        if chunk_is_border_pos::<N>(local, 2*radius - 1) {
+            for &(dir, weight) in offsets {
+                let neighbor_pos = match rule.boundary_mode.resolve(pos + dir, bounds) {
+                    Some(pos) => pos,
+                    None => continue,
+                };
                 let index = utils::pos_to_index(neighbor_pos, bounds);
 
                 let neighbors = neighbors.atomic(index);
                 if inc {
-                    neighbors.fetch_add(1, Ordering::Relaxed);
+                    neighbors.fetch_add(weight, ORDERING);
                 }
                 else {
-                    neighbors.fetch_sub(1, Ordering::Relaxed);
+                    // saturating, see below.
+                    let old = neighbors.fetch_update(ORDERING, ORDERING,
+                        |count| Some(count.saturating_sub(weight)));
+                    debug_assert!(old.unwrap() >= weight, "neighbor count underflow at {}", neighbor_pos);
                 }
             }
         }
         else {
-            for dir in rule.neighbour_method.get_neighbour_iter() {
-                let neighbor_pos = pos + *dir;
+            if cfg!(feature = "moore_fast_path") && fast_path && method == NeighbourMethod::Moore(1) && !rule.count_self {
+                Self::update_neighbors_moore_interior(neighbors, index, bounds, inc);
+                return;
+            }
+
+            // only this task touches these cells, so a separate load & store
+            // is fine, and cheaper than fetch_add.
+            for &(dir, weight) in offsets {
+                let neighbor_pos = pos + dir;
                 let index = utils::pos_to_index(neighbor_pos, bounds);
 
-                let neighbors = neighbors.write(index);
+                let count = neighbors.read(index);
                 if inc {
-                    *neighbors += 1;
+                    neighbors.set(index, count + weight);
                 }
                 else {
-                    *neighbors -= 1;
+                    // a zero count here means the bookkeeping is off somewhere
+                    // else. don't take down the app in release builds.
+                    debug_assert!(count >= weight, "neighbor count underflow at {}", neighbor_pos);
+                    neighbors.set(index, count.saturating_sub(weight));
                 }
             }
         }
     }
 
+    // the interior path of update_neighbors, unrolled for moore r1. all
+    // weights are 1, and the neighbors are the 9 x-rows of 3 cells around
+    // index, minus index itself.
+    fn update_neighbors_moore_interior(neighbors: &Neighbors, index: usize, bounds: IVec3, inc: bool) {
+        let row   = bounds.x as usize;
+        let layer = row * bounds.y as usize;
+
+        let bump = |index: usize| {
+            let count = neighbors.read(index);
+            if inc {
+                neighbors.set(index, count + 1);
+            }
+            else {
+                debug_assert!(count >= 1, "neighbor count underflow at {}", utils::index_to_pos(index, bounds));
+                neighbors.set(index, count.saturating_sub(1));
+            }
+        };
+
+        let rows = [
+            index - layer - row, index - layer, index - layer + row,
+            index         - row, index,         index         + row,
+            index + layer - row, index + layer, index + layer + row,
+        ];
+        for middle in rows {
+            bump(middle - 1);
+            if middle != index {
+                bump(middle);
+            }
+            bump(middle + 1);
+        }
+    }
+
+    // survival checks survival_neighbors if it's Some. cells that become
+    // full get `generation` in born, if births are tracked.
     fn update_values(
-        values: &Values, neighbors: &Values,
-        chunk_index: usize, chunk_radius: usize, bounds: i32,
+        values: &Values, neighbors: &Neighbors, survival_neighbors: Option<&Neighbors>, walls: &[bool],
+        born: &[AtomicU32], generation: u32,
+        chunk_index: usize, chunk_counts: IVec3, bounds: IVec3,
         rule: &Rule,
         spawns: &mut Vec<usize>, deaths: &mut Vec<usize>,
-    ) {
-        let chunk_pos = CHUNK_SIZE as i32 * utils::index_to_pos(chunk_index, chunk_radius as i32);
-        for offset in 0..CHUNK_CELL_COUNT {
-            let pos   = chunk_pos + chunk_offset_to_pos(offset);
+    ) -> (u64, usize, bool) {
+        let mut hash = 0u64;
+        let mut live = 0;
+        let mut changed = false;
+        let chunk_pos = N as i32 * utils::index_to_pos(chunk_index, chunk_counts);
+        for offset in 0..N*N*N {
+            let pos   = chunk_pos + chunk_offset_to_pos::<N>(offset);
             let index = utils::pos_to_index(pos, bounds);
 
-            let value     = values.write(index);
+            let old_value = values.read(index);
+            let mut value = old_value;
             let neighbors = neighbors.read(index);
 
-            if cell_is_dead(*value) {
+            if walls.get(index) == Some(&true) {
+                // keeps its full value.
+            }
+            else if let Some(transitions) = &rule.transitions {
+                let next = transitions.next(value, neighbors);
+                if next == rule.states && value != rule.states {
+                    spawns.push(index);
+                }
+                if value == rule.states && next != rule.states {
+                    deaths.push(index);
+                }
+                value = next;
+            }
+            else if cell_is_dead(value) {
                 if rule.birth_rule.in_range(neighbors) {
-                    *value = rule.states;
+                    value = rule.states;
                     spawns.push(index);
                 }
             }
             else {
-                if *value < rule.states || !rule.survival_rule.in_range(neighbors) {
-                    if *value == rule.states {
+                let survival_neighbors = match survival_neighbors {
+                    Some(survival_neighbors) => survival_neighbors.read(index),
+                    None => neighbors,
+                };
+                if value < rule.states || !rule.survival_rule.in_range(survival_neighbors) {
+                    if value == rule.states {
                         deaths.push(index);
                     }
 
-                    *value -= 1;
+                    value = rule.death_mode.next(value);
+                }
+            }
+            if value != old_value {
+                values.set(index, value);
+                changed = true;
+                if value == rule.states {
+                    if let Some(born) = born.get(index) {
+                        born.store(generation, ORDERING);
+                    }
                 }
             }
+
+            if !cell_is_dead(value) {
+                hash = hash.wrapping_add(cell_hash(index, value));
+                live += 1;
+            }
         }
+        (hash, live, changed)
     }
 
-    pub fn update(&mut self, rule: &Rule, tasks: &TaskPool) {
-        // update values.
-        let mut value_tasks = vec![];
-        for chunk_index in 0..self.chunk_count {
-            let values    = self.values.clone();
-            let neighbors = self.neighbors.clone();
-            let chunk_radius = self.chunk_radius;
-            let bounds = self.bounds();
-
-            let rule = rule.clone(); // shrug
-            let mut chunk_spawns = vec![];
-            let mut chunk_deaths = vec![];
-
-            value_tasks.push(tasks.spawn(async move {
-                Self::update_values(
-                    &values, &neighbors,
-                    chunk_index, chunk_radius, bounds,
-                    &rule,
-                    &mut chunk_spawns, &mut chunk_deaths);
-                (chunk_spawns, chunk_deaths)
-            }));
+    // marks the chunks that the neighbor update of the cell at `index` can
+    // reach. that's only its own chunk, unless it's within the radius of the
+    // chunk border. the larger radius, with a survival neighbourhood.
+    fn mark_dirty_around(dirty: &[AtomicBool], index: usize, chunk_counts: IVec3, rule: &Rule) {
+        let size = N as i32;
+        let pos = utils::index_to_pos(index, chunk_counts * size);
+        let radius = rule.max_radius() as i32;
+        if !chunk_is_border_pos::<N>(pos % size, radius - 1) {
+            dirty[utils::pos_to_index(pos / size, chunk_counts)].store(true, ORDERING);
+            return;
+        }
+
+        // the bounds are whole chunks, so wrapping positions wraps chunks.
+        // clamped positions stay in the chunks at the border, which are in
+        // range anyway.
+        let wraps = match rule.boundary_mode {
+            BoundaryMode::Wrap => BVec3::new(true, true, true),
+            BoundaryMode::WrapAxes(axes) => axes,
+            BoundaryMode::Clamp | BoundaryMode::Dead => BVec3::new(false, false, false),
+        };
+        let chunks = move |pos: i32, count: i32, wraps: bool| {
+            ((pos - radius).div_euclid(size)..=(pos + radius).div_euclid(size))
+                .filter_map(move |chunk|
+                    if wraps { Some(chunk.rem_euclid(count)) }
+                    else if (0..count).contains(&chunk) { Some(chunk) }
+                    else { None })
+        };
+        for z in chunks(pos.z, chunk_counts.z, wraps.z) {
+            for y in chunks(pos.y, chunk_counts.y, wraps.y) {
+                for x in chunks(pos.x, chunk_counts.x, wraps.x) {
+                    let chunk = utils::pos_to_index(ivec3(x, y, z), chunk_counts);
+                    dirty[chunk].store(true, ORDERING);
+                }
+            }
         }
+    }
+
+    pub fn update(&mut self, rule: &Rule, tasks: &TaskPool) -> StepTimings {
+        future::block_on(self.update_async(rule, tasks))
+    }
+
+    // yields between the value & neighbor phase, so an executor that polls
+    // this once per frame spreads a step over two frames.
+    pub async fn update_async(&mut self, rule: &Rule, tasks: &TaskPool) -> StepTimings {
+        let t0 = Instant::now();
+        let batch_size = match self.max_tasks {
+            Some(max_tasks) => self.batch_size.max(utils::div_ceil(self.chunk_count, max_tasks.max(1))),
+            None => self.batch_size,
+        }.max(1);
+
+        if self.history_length > 0 {
+            while self.history.len() >= self.history_length {
+                self.history.pop_front();
+            }
+            let runs = if self.empty { vec![] } else { self.live_runs() };
+            self.history.push_back(Snapshot { runs, rule: rule.clone() });
+        }
+
+        // an empty grid stays empty, unless walls or the rule spawn cells.
+        // only the bookkeeping of an update is done.
+        if self.empty && self.walls.is_empty() && rule.keeps_empty() {
+            self.last_births = 0;
+            self.last_deaths = 0;
+            self.stats.record(self.generation + 1, 0, 0, 0);
+            if self.state_hashes.len() == STABLE_HISTORY {
+                self.state_hashes.pop_front();
+            }
+            self.state_hashes.push_back(0);
+            self.generation += 1;
+            self.last_step = t0.elapsed();
+            return StepTimings::default();
+        }
+
+        if let Some(table) = &rule.margolus {
+            return self.update_margolus(rule, table, t0);
+        }
+
+        if self.last_rule.as_ref() != Some(rule) {
+            self.mark_all_dirty();
+            self.last_rule = Some(rule.clone());
+        }
+        let offsets = &self.neighbor_offsets(rule);
+        // the chunks to update. the tasks set the flags for the next update.
+        let active: Vec<bool> = self.dirty.iter_mut()
+            .map(|dirty| std::mem::replace(dirty.get_mut(), false))
+            .collect();
+
+        // update values.
+        // the tasks are scoped, so they can borrow the cells & the rule.
+        let values    = &self.values;
+        let neighbors = &self.neighbors;
+        let survival_neighbors = offsets.survival.is_some().then(|| &self.survival_neighbors);
+        let walls     = &self.walls[..];
+        let born      = &self.born[..];
+        let generation = (self.generation + 1) as u32;
+        let dirty     = &self.dirty[..];
+        let active    = &active[..];
+        let summaries = &self.chunk_summaries[..];
+        let chunk_counts = self.chunk_counts;
+        let chunk_count  = self.chunk_count;
+        let bounds = self.bounds();
+        let value_results = tasks.scope(|scope| {
+            for batch_start in (0..chunk_count).step_by(batch_size) {
+                let batch_end = (batch_start + batch_size).min(chunk_count);
+
+                scope.spawn(async move {
+                    let mut chunk_spawns = vec![];
+                    let mut chunk_deaths = vec![];
+                    let mut updated = vec![];
+                    let mut hash = 0u64;
+                    let mut live = 0;
+                    for chunk_index in batch_start..batch_end {
+                        let (chunk_hash, chunk_live) =
+                            if active[chunk_index] {
+                                let (chunk_hash, chunk_live, changed) = Self::update_values(
+                                    values, neighbors, survival_neighbors, walls,
+                                    born, generation,
+                                    chunk_index, chunk_counts, bounds,
+                                    rule,
+                                    &mut chunk_spawns, &mut chunk_deaths);
+                                if changed {
+                                    dirty[chunk_index].store(true, ORDERING);
+                                }
+                                updated.push((chunk_index, chunk_hash, chunk_live));
+                                (chunk_hash, chunk_live)
+                            }
+                            else { summaries[chunk_index] };
+                        hash = hash.wrapping_add(chunk_hash);
+                        live += chunk_live;
+                    }
+                    (chunk_spawns, chunk_deaths, updated, hash, live)
+                });
+            }
+        });
 
         // collect spawns & deaths.
         let mut chunk_spawns = vec![];
         let mut chunk_deaths = vec![];
-        for task in value_tasks {
-            let (spawns, deaths) = future::block_on(task);
+        let mut state_hash = 0u64;
+        let mut live = 0;
+        for (spawns, deaths, updated, hash, chunk_live) in value_results {
             chunk_spawns.push(spawns);
             chunk_deaths.push(deaths);
+            for (chunk_index, chunk_hash, chunk_live) in updated {
+                self.chunk_summaries[chunk_index] = (chunk_hash, chunk_live);
+            }
+            state_hash = state_hash.wrapping_add(hash);
+            live += chunk_live;
+        }
+
+        self.last_births = chunk_spawns.iter().map(Vec::len).sum();
+        self.last_deaths = chunk_deaths.iter().map(Vec::len).sum();
+        self.stats.record(self.generation + 1, live, self.last_births, self.last_deaths);
+
+        self.send_events(chunk_spawns.iter().flatten(), chunk_deaths.iter().flatten());
+
+        if self.state_hashes.len() == STABLE_HISTORY {
+            self.state_hashes.pop_front();
         }
+        self.state_hashes.push_back(state_hash);
+        self.empty = live == 0;
 
+        let t1 = Instant::now();
+        future::yield_now().await;
+        let t2 = Instant::now();
 
         // update neighbors.
-        let mut neighbor_tasks = vec![];
-        for (spawns, deaths) in chunk_spawns.into_iter().zip(chunk_deaths) {
-            let neighbors = self.neighbors.clone();
-            let bounds = self.bounds();
-            let rule = rule.clone(); // shrug
-
-            neighbor_tasks.push(tasks.spawn(async move {
-                for index in spawns.iter() {
-                    Self::update_neighbors(
-                        &neighbors,
-                        *index, bounds,
-                        &rule, true);
+        // the chunks of a batch are updated by the same task, so the only
+        // races are still at the chunk borders, where atomics are used anyway.
+        let neighbors = &self.neighbors;
+        let survival_neighbors = &self.survival_neighbors;
+        let dirty     = &self.dirty[..];
+        tasks.scope(|scope| {
+            for (spawns, deaths) in chunk_spawns.into_iter().zip(chunk_deaths) {
+                scope.spawn(async move {
+                    for index in spawns.iter() {
+                        Self::update_neighbors(
+                            neighbors, survival_neighbors,
+                            *index, bounds,
+                            rule, offsets, true);
+                        Self::mark_dirty_around(dirty, *index, chunk_counts, rule);
+                    }
+
+                    for index in deaths.iter() {
+                        Self::update_neighbors(
+                            neighbors, survival_neighbors,
+                            *index, bounds,
+                            rule, offsets, false);
+                        Self::mark_dirty_around(dirty, *index, chunk_counts, rule);
+                    }
+                });
+            }
+        });
+
+        self.generation += 1;
+
+        if let Some(symmetry) = rule.enforce_symmetry {
+            self.enforce_symmetry(rule, symmetry);
+        }
+
+        let timings = StepTimings {
+            values:    t1 - t0,
+            neighbors: t2.elapsed(),
+        };
+        self.last_step = timings.values + timings.neighbors;
+        timings
+    }
+
+
+    // the post-step pass of Rule::enforce_symmetry: raises every cell to
+    // the highest value of its images around the center, so cells are only
+    // ever added. the images go through the boundary mode, like stamped
+    // cells (on even bounds, the center is between cells, so one layer
+    // wraps). runs after the update's bookkeeping, so the stats, events &
+    // state hashes don't see the added cells. returns how many there were.
+    fn enforce_symmetry(&mut self, rule: &Rule, symmetry: Symmetry) -> usize {
+        let bounds = self.bounds();
+        let center = self.center();
+        let mut raised: HashMap<usize, u8> = HashMap::new();
+        for (pos, value) in self.live_cells() {
+            for image in symmetry.images(pos - center) {
+                let index = match rule.boundary_mode.resolve(center + image, bounds) {
+                    Some(pos) => utils::pos_to_index(pos, bounds),
+                    None => continue,
+                };
+                if self.values.read(index) < value && !self.is_wall(index) {
+                    let raised = raised.entry(index).or_insert(0);
+                    *raised = (*raised).max(value);
                 }
+            }
+        }
 
-                for index in deaths.iter() {
-                    Self::update_neighbors(
-                        &neighbors,
-                        *index, bounds,
-                        &rule, false);
+        let offsets = self.neighbor_offsets(rule);
+        for (&index, &value) in raised.iter() {
+            // raised from below, so only ever into the full state.
+            if value == rule.states {
+                Self::update_neighbors(&self.neighbors, &self.survival_neighbors, index, bounds, rule, &offsets, true);
+                self.mark_born(index, self.generation);
+            }
+            self.values.set(index, value);
+            Self::mark_dirty_around(&self.dirty, index, self.chunk_counts, rule);
+        }
+        if !raised.is_empty() {
+            self.empty = false;
+        }
+        raised.len()
+    }
+
+    // the births & deaths of an update, see set_event_sender.
+    fn send_events<'a, I, J>(&mut self, spawns: I, deaths: J)
+        where I: Iterator<Item = &'a usize>, J: Iterator<Item = &'a usize>
+    {
+        let sender = match &self.event_sender {
+            Some(sender) if self.last_births + self.last_deaths > 0 => sender,
+            _ => return,
+        };
+        let bounds = self.bounds();
+        let to_pos = |index: &usize| utils::index_to_pos(*index, bounds);
+        let events = spawns.map(|index| CellEvent::Born(to_pos(index)))
+            .chain(deaths.map(|index| CellEvent::Died(to_pos(index))))
+            .collect();
+        if let Err(TrySendError::Disconnected(_)) = sender.try_send(events) {
+            self.event_sender = None;
+        }
+    }
+
+    // replaces every block by its next (or previous) state, see
+    // MargolusTable. generation g uses blocks starting at g % 2 on all axes.
+    // sequential: the blocks never overlap, but the shifted ones straddle the
+    // chunk borders. blocks with walls or cells past a non wrapping border
+    // stay as they are. returns the spawns & deaths.
+    // the bounds have to be even, or the shifted blocks wouldn't tile a
+    // wrapping grid (the last one would overlap the first). see set_bounds.
+    fn margolus_blocks(&mut self, rule: &Rule, table: &MargolusTable, generation: u64, inverse: bool) -> (Vec<usize>, Vec<usize>) {
+        let bounds = self.bounds();
+        assert!(bounds % 2 == IVec3::ZERO, "margolus blocks need even bounds, not {}", bounds);
+        let offsets = self.neighbor_offsets(rule);
+        let shift = (generation % 2) as i32;
+        let mut spawns = vec![];
+        let mut deaths = vec![];
+        for z in (shift..bounds.z + shift).step_by(2) {
+            for y in (shift..bounds.y + shift).step_by(2) {
+                for x in (shift..bounds.x + shift).step_by(2) {
+                    let mut cells = [0; 8];
+                    let mut block = 0u8;
+                    let mut inside = true;
+                    for (corner, cell) in cells.iter_mut().enumerate() {
+                        let pos = ivec3(x, y, z) + ivec3(
+                            (corner & 1) as i32, ((corner >> 1) & 1) as i32, ((corner >> 2) & 1) as i32);
+                        let pos = match rule.boundary_mode {
+                            // clamping would put two corners on the same cell.
+                            BoundaryMode::Clamp => utils::is_in_bounds(pos, bounds).then(|| pos),
+                            mode => mode.resolve(pos, bounds),
+                        };
+                        *cell = match pos {
+                            Some(pos) => utils::pos_to_index(pos, bounds),
+                            None => { inside = false; break; }
+                        };
+                        if self.walls.get(*cell) == Some(&true) {
+                            inside = false;
+                            break;
+                        }
+                        if self.values.read(*cell) == rule.states {
+                            block |= 1 << corner;
+                        }
+                    }
+                    if !inside {
+                        continue;
+                    }
+
+                    let next = if inverse { table.previous(block) } else { table.next(block) };
+                    for (corner, index) in cells.iter().enumerate() {
+                        let was = block & (1 << corner) != 0;
+                        let is  = next  & (1 << corner) != 0;
+                        if is && !was {
+                            spawns.push(*index);
+                            // like update_values. stepping back doesn't
+                            // restore the ages.
+                            if !inverse {
+                                self.mark_born(*index, generation + 1);
+                            }
+                        }
+                        if was && !is { deaths.push(*index); }
+                        // dying cells aren't part of the block state, they're cleared.
+                        self.values.set(*index, if is { rule.states } else { 0 });
+                    }
                 }
-            }));
+            }
         }
 
-        for task in neighbor_tasks {
-            future::block_on(task);
+        for index in spawns.iter() {
+            Self::update_neighbors(&self.neighbors, &self.survival_neighbors, *index, bounds, rule, &offsets, true);
+        }
+        for index in deaths.iter() {
+            Self::update_neighbors(&self.neighbors, &self.survival_neighbors, *index, bounds, rule, &offsets, false);
         }
+        self.mark_all_dirty();
+        (spawns, deaths)
     }
 
+    // update_async for margolus rules. the bookkeeping is the same, just
+    // without tasks or dirty chunks.
+    fn update_margolus(&mut self, rule: &Rule, table: &MargolusTable, t0: Instant) -> StepTimings {
+        let (spawns, deaths) = self.margolus_blocks(rule, table, self.generation, false);
+        self.last_rule = Some(rule.clone());
 
-    // TEMP: move to sims.
-    #[allow(dead_code)]
-    fn validate(&self, rule: &Rule) {
+        let mut state_hash = 0u64;
+        let mut live = 0;
         for index in 0..self.total_cell_count() {
-            let pos = utils::index_to_pos(index, self.bounds());
+            let value = self.values.read(index);
+            if !cell_is_dead(value) {
+                state_hash = state_hash.wrapping_add(cell_hash(index, value));
+                live += 1;
+            }
+        }
 
-            let mut neighbors = 0;
-            for dir in rule.neighbour_method.get_neighbour_iter() {
-                let neighbor_pos = utils::wrap(pos + *dir, self.bounds());
-                let index = utils::pos_to_index(neighbor_pos, self.bounds());
+        self.last_births = spawns.len();
+        self.last_deaths = deaths.len();
+        self.stats.record(self.generation + 1, live, self.last_births, self.last_deaths);
+        self.send_events(spawns.iter(), deaths.iter());
 
-                let value = self.values.read(index);
-                if value == rule.states {
-                    neighbors += 1;
-                }
+        if self.state_hashes.len() == STABLE_HISTORY {
+            self.state_hashes.pop_front();
+        }
+        self.state_hashes.push_back(state_hash);
+        self.empty = live == 0;
+        self.generation += 1;
+
+        let timings = StepTimings {
+            values:    t0.elapsed(),
+            neighbors: Duration::ZERO,
+        };
+        self.last_step = timings.values;
+        timings
+    }
+
+    // only reads, but the counts are only meaningful between updates. the
+    // survival counts are checked too, if there are any for this rule.
+    pub fn validate(&self, rule: &Rule) -> Vec<NeighbourMismatch> {
+        let is_full = |index| self.values.read(index) == rule.states;
+        let mut mismatches = cells::neighbor_mismatches(rule, self.bounds(),
+            is_full,
+            |index| self.neighbors.read(index));
+        if let Some(survival_rule) = rule.with_survival_neighbours() {
+            if self.survival_rule.as_ref() == Some(&survival_rule) {
+                mismatches.extend(cells::neighbor_mismatches(&survival_rule, self.bounds(),
+                    is_full,
+                    |index| self.survival_neighbors.read(index)));
             }
+        }
+        mismatches
+    }
+
+    // the repair for validate's mismatches.
+    pub fn recount_neighbours(&mut self, rule: &Rule) {
+        let counts = cells::count_neighbors(rule, self.bounds(),
+            |index| self.values.read(index) == rule.states);
+        for (index, count) in counts.into_iter().enumerate() {
+            self.neighbors.set(index, count);
+        }
+        // the survival counts are recounted by neighbor_offsets.
+        self.survival_rule = None;
+        self.neighbor_offsets(rule);
+        self.mark_all_dirty();
+    }
+
+    // true if the live cells after the last update are the same as after one
+    // of the few updates before. so still lifes and short oscillators.
+    pub fn is_stable(&self) -> bool {
+        self.stable_period().is_some()
+    }
+
+    // generations until the live cells repeat, 1 for still lifes.
+    pub fn stable_period(&self) -> Option<u64> {
+        let last = self.state_hashes.back()?;
+        self.state_hashes.iter().rev().skip(1)
+            .position(|hash| hash == last)
+            .map(|index| index as u64 + 1)
+    }
+
+    // steps as many generations as fit in `budget`, at least one. returns
+    // how many. for fast forwarding without stalling the frame.
+    pub fn step_for(&mut self, rule: &Rule, tasks: &TaskPool, budget: Duration) -> u32 {
+        cells::step_for(budget, || { self.update(rule, tasks); })
+    }
+
+    // steps until the grid dies out, fills up or becomes stable (checked in
+    // that order), or the generation reaches `max_generation`. for
+    // classifying rules without a window.
+    pub fn run_until(&mut self, rule: &Rule, tasks: &TaskPool, max_generation: u64) -> RunOutcome {
+        let total = self.total_cell_count().max(1);
+        while self.generation < max_generation {
+            self.update(rule, tasks);
+            let generation = self.generation;
 
-            assert_eq!(neighbors, self.neighbors.read(index));
+            // the update just recorded the live cells, no need to count.
+            let live = self.stats.rows().last().map_or(0, |row| row.cell_count);
+            if live == 0 {
+                return RunOutcome::Extinct(generation);
+            }
+            if live as f32 / total as f32 >= RUN_FILLED_RATIO {
+                return RunOutcome::Filled(generation);
+            }
+            if let Some(period) = self.stable_period() {
+                return RunOutcome::Stable(generation, period);
+            }
         }
+        RunOutcome::MaxReached
     }
 
-    pub fn spawn_noise(&mut self, rule: &Rule) {
+    pub fn spawn_noise(&mut self, rule: &Rule, seed: Option<u64>) {
         let center = self.center();
+        self.spawn_noise_at(rule, center, rule.noise_radius(self.bounds()), seed);
+    }
+
+    // seeds the cube of `radius` around `center`, which can be anywhere.
+    // parts outside the bounds are wrapped/clamped/dropped by the boundary mode.
+    pub fn spawn_noise_at(&mut self, rule: &Rule, center: IVec3, radius: i32, seed: Option<u64>) {
         let bounds = self.bounds();
+        self.state_hashes.clear();
+        self.mark_all_dirty();
 
-        utils::make_some_noise_default(center, |pos| {
-            let index = utils::pos_to_index(utils::wrap(pos, bounds), self.bounds());
-            let value = self.values.write(index);
-            if cell_is_dead(*value) {
-                *value = rule.states;
+        let offsets = self.neighbor_offsets(rule);
+        utils::make_some_noise_in(center, radius, rule.seed_shape, rule.noise_kind, rule.noise_density, seed, |pos| {
+            let pos = match rule.seed_pos(pos, bounds) {
+                Some(pos) => pos,
+                None => return,
+            };
+            let index = utils::pos_to_index(pos, bounds);
+            if cell_is_dead(self.values.read(index)) {
+                self.values.set(index, rule.states);
+                self.mark_born(index, self.generation);
+                self.empty = false;
                 Self::update_neighbors(
-                    &self.neighbors,
+                    &self.neighbors, &self.survival_neighbors,
                     index, self.bounds(),
-                    rule, true);
+                    rule, &offsets, true);
             }
         });
+
+        debug_assert_eq!(self.validate(rule), vec![]);
+        self.capture_initial();
+    }
+
+    // small deterministic seeds at the center, in the full state.
+    pub fn spawn_single(&mut self, rule: &Rule) {
+        let center = self.center();
+        self.stamp_pattern(&[(IVec3::ZERO, rule.states)], center, rule);
+        self.capture_initial();
+    }
+
+    // the center & `arm` cells along each axis in both directions.
+    pub fn spawn_cross(&mut self, rule: &Rule, arm: i32) {
+        let mut cells = vec![(IVec3::ZERO, rule.states)];
+        for axis in [IVec3::X, IVec3::Y, IVec3::Z] {
+            for distance in 1..=arm {
+                cells.push(( distance*axis, rule.states));
+                cells.push((-distance*axis, rule.states));
+            }
+        }
+        let center = self.center();
+        self.stamp_pattern(&cells, center, rule);
+        self.capture_initial();
+    }
+
+    // a solid `size`^3 cube, centered (rounded down for even sizes).
+    pub fn spawn_cube(&mut self, rule: &Rule, size: i32) {
+        let size = size.max(1);
+        let cells: Vec<_> = (0..size*size*size)
+            .map(|index| (utils::index_to_pos(index as usize, IVec3::splat(size)), rule.states))
+            .collect();
+        let origin = self.center() - IVec3::splat(size/2);
+        self.stamp_pattern(&cells, origin, rule);
+        self.capture_initial();
+    }
+
+    // random cells in the positive octant around the center, mirrored
+    // according to `symmetry`.
+    pub fn spawn_symmetric(&mut self, rule: &Rule, seed: u64, symmetry: Symmetry) {
+        let radius = utils::NOISE_RADIUS;
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let mut cells = vec![];
+        for _ in 0..radius.pow(3) {
+            let offset = ivec3(
+                rng.gen_range(0..=radius),
+                rng.gen_range(0..=radius),
+                rng.gen_range(0..=radius));
+            for image in symmetry.images(offset) {
+                cells.push((image, rule.states));
+            }
+        }
+
+        // stamping fixes the neighbor counts, and ignores duplicates.
+        let center = self.center();
+        self.stamp_pattern(&cells, center, rule);
+        self.capture_initial();
+    }
+
+    // places `cells` (eg: from patterns::load_rle3) relative to `origin`,
+    // overwriting what's there. values are clamped to the rule's states.
+    pub fn stamp_pattern(&mut self, cells: &[(IVec3, u8)], origin: IVec3, rule: &Rule) {
+        let bounds = self.bounds();
+        self.state_hashes.clear();
+
+        let offsets = self.neighbor_offsets(rule);
+        for (pos, value) in cells {
+            let pos = match rule.boundary_mode.resolve(origin + *pos, bounds) {
+                Some(pos) => pos,
+                None => continue,
+            };
+            let index = utils::pos_to_index(pos, bounds);
+            if self.is_wall(index) {
+                continue;
+            }
+            let old_value = self.values.read(index);
+            let new_value = (*value).min(rule.states);
+
+            // only cells in the full state count as neighbors.
+            if old_value == rule.states && new_value != rule.states {
+                Self::update_neighbors(&self.neighbors, &self.survival_neighbors, index, bounds, rule, &offsets, false);
+            }
+            if new_value == rule.states && old_value != rule.states {
+                Self::update_neighbors(&self.neighbors, &self.survival_neighbors, index, bounds, rule, &offsets, true);
+                self.mark_born(index, self.generation);
+            }
+            self.values.set(index, new_value);
+            if new_value != 0 {
+                self.empty = false;
+            }
+            // only the chunks around the cell, so painting cells one by one
+            // doesn't make the next update a full one.
+            if new_value != old_value {
+                Self::mark_dirty_around(&self.dirty, index, self.chunk_counts, rule);
+            }
+        }
+    }
+
+    // one cell, like stamp_pattern. `pos` goes through the boundary mode, so
+    // it can be out of bounds with Wrap. walls stay as they are.
+    pub fn set_cell(&mut self, pos: IVec3, value: u8, rule: &Rule) {
+        self.stamp_pattern(&[(pos, value)], IVec3::ZERO, rule);
+    }
+
+    #[allow(dead_code)]
+    pub fn clear_cell(&mut self, pos: IVec3, rule: &Rule) {
+        self.set_cell(pos, 0, rule);
+    }
+
+    // a wall is a full cell that's never updated, so it always counts as a
+    // neighbor, eg: for containers & mazes. removing a wall leaves a normal
+    // full cell. walls don't survive resizes.
+    pub fn set_wall(&mut self, rule: &Rule, pos: IVec3, wall: bool) {
+        if !utils::is_in_bounds(pos, self.bounds()) {
+            return;
+        }
+        if self.walls.is_empty() {
+            if !wall {
+                return;
+            }
+            self.walls = vec![false; self.total_cell_count()];
+        }
+
+        let index = utils::pos_to_index(pos, self.bounds());
+        self.walls[index] = false;
+        if wall {
+            self.stamp_pattern(&[(pos, rule.states)], IVec3::ZERO, rule);
+        }
+        self.walls[index] = wall;
+        // a removed wall is a normal cell again, which can die.
+        self.mark_all_dirty();
+    }
+
+    pub fn is_wall(&self, index: usize) -> bool {
+        self.walls.get(index) == Some(&true)
+    }
+
+    fn live_runs(&self) -> Vec<(u32, u32, u8)> {
+        let mut runs: Vec<(u32, u32, u8)> = vec![];
+        for index in 0..self.total_cell_count() {
+            let value = self.values.read(index);
+            if cell_is_dead(value) {
+                continue;
+            }
+
+            match runs.last_mut() {
+                Some((start, length, run_value))
+                    if *run_value == value && (*start + *length) as usize == index => {
+                    *length += 1;
+                }
+                _ => runs.push((index as u32, 1, value)),
+            }
+        }
+        runs
+    }
+
+    // the spawn_* functions call this. only the spawns before the first
+    // update make up the start, later ones are edits.
+    fn capture_initial(&mut self) {
+        if self.generation == 0 {
+            self.initial = Some((self.bounds(), self.live_runs()));
+        }
+    }
+
+    // resets to the start captured by the spawn_* functions, so a different
+    // rule can run from the same cells. values are clamped to the rule's
+    // states. walls are gone, like after any reset. false if there's no
+    // start for these bounds.
+    pub fn reset_to_initial(&mut self, rule: &Rule) -> bool {
+        let runs = match &self.initial {
+            Some((bounds, runs)) if *bounds == self.bounds() => runs.clone(),
+            _ => return false,
+        };
+
+        let bounds = self.set_bounds(self.bounds());
+        let offsets = self.neighbor_offsets(rule);
+        for (start, length, value) in runs.iter().copied() {
+            let value = value.min(rule.states);
+            for index in start as usize..(start + length) as usize {
+                self.values.set(index, value);
+                if value == rule.states {
+                    Self::update_neighbors(&self.neighbors, &self.survival_neighbors, index, bounds, rule, &offsets, true);
+                }
+            }
+        }
+        self.empty = runs.is_empty();
+        true
+    }
+
+    // restores the cells from before the last update. returns false if
+    // there's no history left (or it's disabled).
+    pub fn step_back(&mut self) -> bool {
+        let snapshot = match self.history.pop_back() {
+            Some(snapshot) => snapshot,
+            None => return self.step_back_margolus(),
+        };
+
+        let bounds = self.bounds();
+        for index in 0..self.total_cell_count() {
+            self.values.set(index, 0);
+            self.neighbors.set(index, 0);
+        }
+        // recounted from the cleared values by neighbor_offsets.
+        self.survival_rule = None;
+
+        let rule = &snapshot.rule;
+        let offsets = self.neighbor_offsets(rule);
+        for (start, length, value) in snapshot.runs.iter().copied() {
+            for index in start as usize..(start + length) as usize {
+                self.values.set(index, value);
+                if value == rule.states {
+                    Self::update_neighbors(&self.neighbors, &self.survival_neighbors, index, bounds, rule, &offsets, true);
+                }
+            }
+        }
+
+        self.generation = self.generation.saturating_sub(1);
+        self.empty = snapshot.runs.is_empty();
+        self.state_hashes.clear();
+        self.mark_all_dirty();
+        self.last_births = 0;
+        self.last_deaths = 0;
+        true
+    }
+
+    // margolus rules are reversible, so they can step back past the history.
+    // exact, unless cells were set since.
+    fn step_back_margolus(&mut self) -> bool {
+        let rule = match &self.last_rule {
+            Some(rule) if rule.margolus.is_some() && self.generation > 0 => rule.clone(),
+            _ => return false,
+        };
+        let table = rule.margolus.as_ref().unwrap();
+        self.margolus_blocks(&rule, table, self.generation - 1, true);
+
+        self.generation -= 1;
+        self.empty = self.cell_count() == 0;
+        self.state_hashes.clear();
+        self.last_births = 0;
+        self.last_deaths = 0;
+        true
+    }
+
+    /*
+        save format (little endian):
+            - magic "CA3D"
+            - bounds: 3 x u32, rule fingerprint: u64, run count: u32
+            - runs: (start index: u32, length: u32, value: u8)
+              a run is a sequence of consecutive live cells with the same value.
+    */
+    pub fn save_to_writer<W: Write>(&self, w: &mut W, rule: &Rule) -> io::Result<()> {
+        let runs = self.live_runs();
+
+        w.write_all(SAVE_MAGIC)?;
+        let bounds = self.bounds();
+        for extent in [bounds.x, bounds.y, bounds.z] {
+            w.write_all(&(extent as u32).to_le_bytes())?;
+        }
+        w.write_all(&rule.fingerprint().to_le_bytes())?;
+        w.write_all(&(runs.len() as u32).to_le_bytes())?;
+        for (start, length, value) in runs {
+            w.write_all(&start.to_le_bytes())?;
+            w.write_all(&length.to_le_bytes())?;
+            w.write_all(&[value])?;
+        }
+        Ok(())
+    }
+
+    // the whole save is read & checked before anything is loaded, so on an
+    // error the sim keeps the cells it had.
+    pub fn load_from_reader<R: Read>(&mut self, r: &mut R, rule: &Rule) -> io::Result<()> {
+        fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+            let mut bytes = [0; 4];
+            r.read_exact(&mut bytes)?;
+            Ok(u32::from_le_bytes(bytes))
+        }
+        fn invalid(message: String) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        }
+
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != SAVE_MAGIC {
+            return Err(invalid("not a cell grid save".into()));
+        }
+
+        let saved_bounds = ivec3(
+            read_u32(r)? as i32,
+            read_u32(r)? as i32,
+            read_u32(r)? as i32);
+        let mut fingerprint = [0; 8];
+        r.read_exact(&mut fingerprint)?;
+        if u64::from_le_bytes(fingerprint) != rule.fingerprint() {
+            return Err(invalid("save was made with a different rule".into()));
+        }
+
+        // the atomic sim rounds its bounds up to whole chunks (like
+        // set_bounds). loading a save of a different size would silently
+        // wrap positions, so refuse.
+        let bounds = bounds_to_chunk_counts::<N>(utils::clamp_bounds(saved_bounds)) * N as i32;
+        if bounds != saved_bounds {
+            return Err(invalid(format!(
+                "save has bounds {:?}, but this sim only supports {:?}",
+                saved_bounds, bounds)));
+        }
+
+        let run_count = read_u32(r)?;
+        let mut runs = vec![];
+        for _ in 0..run_count {
+            let start  = read_u32(r)? as usize;
+            let length = read_u32(r)? as usize;
+            let mut value = [0; 1];
+            r.read_exact(&mut value)?;
+            let value = value[0];
+
+            if start + length > utils::volume(bounds) {
+                return Err(invalid(format!("run at {} is out of bounds", start)));
+            }
+            if cell_is_dead(value) || value > rule.states {
+                return Err(invalid(format!("run at {} has invalid value {}", start, value)));
+            }
+            runs.push((start, length, value));
+        }
+
+        self.set_bounds(bounds);
+        // before the values are loaded, see neighbor_offsets.
+        let offsets = self.neighbor_offsets(rule);
+        for (start, length, value) in runs {
+            for index in start..start + length {
+                let old_value = self.values.read(index);
+                self.values.set(index, value);
+                // neighbors aren't saved, rebuild them from the loaded values.
+                // runs only overlap in hand made saves, a cell still only
+                // counts once.
+                let full = value == rule.states;
+                if full != (old_value == rule.states) {
+                    Self::update_neighbors(
+                        &self.neighbors, &self.survival_neighbors,
+                        index, bounds,
+                        rule, &offsets, full);
+                }
+            }
+            self.empty = false;
+        }
+        Ok(())
+    }
+
+    // one unit cube per live cell, at the same positions as in the renderer.
+    // `color` gets (position, value, neighbors), like ColorMethod::color, and
+    // is written as vertex colors (`v x y z r g b`, which blender imports).
+    pub fn export_obj<W: Write, F: Fn(IVec3, u8, u16) -> Color>(
+        &self, w: &mut W, rule: &Rule, color: F
+    ) -> io::Result<()> {
+        writeln!(w, "# {}", rule.to_rulestring())?;
+
+        let bounds = self.bounds();
+        let center = self.center();
+        let mut vertex_count = 0;
+        for index in 0..self.total_cell_count() {
+            let value = self.values.read(index);
+            if cell_is_dead(value) {
+                continue;
+            }
+
+            let pos   = utils::index_to_pos(index, bounds);
+            let color = color(pos, value, self.neighbors.read(index));
+            let position = (pos - center).as_vec3();
+            for corner in 0..8 {
+                let offset = vec3(
+                    (corner & 1) as f32,
+                    ((corner >> 1) & 1) as f32,
+                    ((corner >> 2) & 1) as f32) - Vec3::splat(0.5);
+                let vertex = position + offset;
+                writeln!(w, "v {} {} {} {} {} {}",
+                    vertex.x, vertex.y, vertex.z,
+                    color.r(), color.g(), color.b())?;
+            }
+            for face in CUBE_FACES.iter() {
+                // obj indices start at 1.
+                writeln!(w, "f {} {} {} {}",
+                    vertex_count + face[0] + 1, vertex_count + face[1] + 1,
+                    vertex_count + face[2] + 1, vertex_count + face[3] + 1)?;
+            }
+            vertex_count += 8;
+        }
+        Ok(())
+    }
+
+    // one point per live cell, at the cell's center like export_obj, with
+    // `color` as 8 bit rgb. lighter than the cubes if you only need points.
+    pub fn export_ply<W: Write, F: Fn(IVec3, u8, u16) -> Color>(
+        &self, w: &mut W, rule: &Rule, format: PlyFormat, color: F
+    ) -> io::Result<()> {
+        let bounds = self.bounds();
+        let center = self.center();
+        // the header needs the count up front.
+        let live: Vec<usize> = (0..self.total_cell_count())
+            .filter(|index| !cell_is_dead(self.values.read(*index)))
+            .collect();
+
+        writeln!(w, "ply")?;
+        writeln!(w, "format {} 1.0", match format {
+            PlyFormat::Ascii              => "ascii",
+            PlyFormat::BinaryLittleEndian => "binary_little_endian",
+        })?;
+        writeln!(w, "comment {}", rule.to_rulestring())?;
+        writeln!(w, "element vertex {}", live.len())?;
+        for axis in ["x", "y", "z"] {
+            writeln!(w, "property float {}", axis)?;
+        }
+        for channel in ["red", "green", "blue"] {
+            writeln!(w, "property uchar {}", channel)?;
+        }
+        writeln!(w, "end_header")?;
+
+        for index in live {
+            let pos   = utils::index_to_pos(index, bounds);
+            let color = color(pos, self.values.read(index), self.neighbors.read(index));
+            let position = (pos - center).as_vec3();
+            let rgb = [color.r(), color.g(), color.b()]
+                .map(|channel| (channel * 255.0).round().clamp(0.0, 255.0) as u8);
+            match format {
+                PlyFormat::Ascii => {
+                    writeln!(w, "{} {} {} {} {} {}",
+                        position.x, position.y, position.z,
+                        rgb[0], rgb[1], rgb[2])?;
+                }
+                PlyFormat::BinaryLittleEndian => {
+                    for coord in position.to_array() {
+                        w.write_all(&coord.to_le_bytes())?;
+                    }
+                    w.write_all(&rgb)?;
+                }
+            }
+        }
+        Ok(())
     }
 }
 
 
-impl crate::cells::Sim for LeddooAtomic {
+impl<const N: usize> crate::cells::Sim for LeddooAtomicChunked<N> {
     fn update(&mut self, rule: &Rule, task_pool: &TaskPool) {
         self.update(rule, task_pool);
     }
 
+    fn step_for(&mut self, rule: &Rule, task_pool: &TaskPool, budget: Duration) -> u32 {
+        self.step_for(rule, task_pool, budget)
+    }
+
     fn render(&self, renderer: &mut CellRenderer) {
+        // no walls either, they're live.
+        if self.empty {
+            renderer.clear();
+            return;
+        }
+
         for index in 0..self.total_cell_count() {
             renderer.set(index,
                 self.values.read(index),
                 self.neighbors.read(index));
         }
+
+        if self.track_births {
+            renderer.ages.resize(renderer.cell_count(), 0);
+            for index in 0..self.total_cell_count() {
+                renderer.ages[index] = self.age(index).unwrap_or(0);
+            }
+        }
+
+        if !self.walls.is_empty() {
+            renderer.colors.resize(renderer.cell_count(), None);
+            for index in 0..self.total_cell_count() {
+                if self.is_wall(index) {
+                    renderer.colors[index] = Some(WALL_COLOR);
+                }
+            }
+        }
     }
 
-    fn spawn_noise(&mut self, rule: &Rule) {
-        self.spawn_noise(rule);
+    fn spawn_noise(&mut self, rule: &Rule, seed: Option<u64>) {
+        self.spawn_noise(rule, seed);
     }
 
     fn cell_count(&self) -> usize {
         self.cell_count()
     }
 
-    fn bounds(&self) -> i32 {
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn fill_ratio(&self) -> f32 {
+        self.fill_ratio()
+    }
+
+    fn is_stable(&self) -> bool {
+        self.is_stable()
+    }
+
+    fn last_births(&self) -> usize {
+        self.last_births
+    }
+
+    fn step_back(&mut self) -> bool {
+        self.step_back()
+    }
+
+    fn reset_to_initial(&mut self, rule: &Rule) -> bool {
+        self.reset_to_initial(rule)
+    }
+
+    fn set_track_births(&mut self, track: bool) {
+        self.set_track_births(track);
+    }
+
+    fn set_history_length(&mut self, length: usize) {
+        self.set_history_length(length);
+    }
+
+    fn last_deaths(&self) -> usize {
+        self.last_deaths
+    }
+
+    fn bounds(&self) -> IVec3 {
         self.bounds()
     }
 
-    fn set_bounds(&mut self, new_bounds: i32) -> i32 {
+    fn set_bounds(&mut self, new_bounds: IVec3) -> IVec3 {
         self.set_bounds(new_bounds)
     }
+
+    fn resize_preserving(&mut self, rule: &Rule, new_bounds: IVec3) -> IVec3 {
+        self.resize_preserving(rule, new_bounds)
+    }
+
+    fn validate(&self, rule: &Rule) -> Option<Vec<NeighbourMismatch>> {
+        Some(self.validate(rule))
+    }
+
+    fn recount_neighbours(&mut self, rule: &Rule) -> bool {
+        self.recount_neighbours(rule);
+        true
+    }
+
+    fn last_step_millis(&self) -> f32 {
+        self.last_step.as_secs_f32() * 1000.0
+    }
+
+    fn set_cell(&mut self, rule: &Rule, pos: IVec3, value: u8) -> bool {
+        if !utils::is_in_bounds(pos, self.bounds()) {
+            return false;
+        }
+        self.set_cell(pos, value, rule);
+        true
+    }
+
+    fn load_cells(&mut self, rule: &Rule, cells: &[(IVec3, u8)]) -> bool {
+        let bounds = self.bounds();
+        let cells: Vec<_> = cells.iter().copied()
+            .filter(|(pos, _)| utils::is_in_bounds(*pos, bounds))
+            .collect();
+        self.stamp_pattern(&cells, IVec3::ZERO, rule);
+        true
+    }
+
+    fn save_to_writer(&self, mut w: &mut dyn Write, rule: &Rule) -> io::Result<()> {
+        self.save_to_writer(&mut w, rule)
+    }
+
+    fn load_from_reader(&mut self, mut r: &mut dyn Read, rule: &Rule) -> io::Result<()> {
+        self.load_from_reader(&mut r, rule)
+    }
 }
 
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_rule() -> Rule {
+        Rule::from_rulestring("B4/S4/5/M").unwrap()
+    }
+
+    fn noise_sim(rule: &Rule, bounds: IVec3, seed: u64) -> LeddooAtomic {
+        let mut sim = LeddooAtomic::new();
+        sim.set_bounds(bounds);
+        sim.spawn_noise(rule, Some(seed));
+        sim
+    }
+
+    // header of a save for `bounds`, followed by the runs.
+    fn save_bytes(rule: &Rule, bounds: IVec3, runs: &[(u32, u32, u8)]) -> Vec<u8> {
+        let mut bytes = SAVE_MAGIC.to_vec();
+        for extent in [bounds.x, bounds.y, bounds.z] {
+            bytes.extend((extent as u32).to_le_bytes());
+        }
+        bytes.extend(rule.fingerprint().to_le_bytes());
+        bytes.extend((runs.len() as u32).to_le_bytes());
+        for (start, length, value) in runs {
+            bytes.extend(start.to_le_bytes());
+            bytes.extend(length.to_le_bytes());
+            bytes.push(*value);
+        }
+        bytes
+    }
+
+    #[test]
+    fn noise_is_deterministic_for_a_seed() {
+        let rule = test_rule();
+        let a = noise_sim(&rule, IVec3::splat(32), 7);
+        let b = noise_sim(&rule, IVec3::splat(32), 7);
+        assert!(a.cell_count() > 0);
+        assert_eq!(a.cell_count(), b.cell_count());
+        assert_eq!(cells::live_cells(&a), cells::live_cells(&b));
+        assert_eq!(cells::first_difference(&a, &b), None);
+
+        let c = noise_sim(&rule, IVec3::splat(32), 8);
+        assert_ne!(cells::live_cells(&a), cells::live_cells(&c));
+    }
+
+    // a 2x2x3 column against the z = max face. with wrapping, (10, 10, 0)
+    // would see 4 of its cells through the face.
+    fn step_z_border_column(boundary_mode: BoundaryMode) -> Vec<(IVec3, u8)> {
+        let mut rule = test_rule();
+        rule.boundary_mode = boundary_mode;
+        let bounds = IVec3::splat(32);
+        let mut sim = LeddooAtomic::new();
+        sim.set_bounds(bounds);
+        for z in bounds.z - 3..bounds.z {
+            for (x, y) in [(10, 10), (10, 11), (11, 10), (11, 11)] {
+                sim.set_cell(ivec3(x, y, z), rule.states, &rule);
+            }
+        }
+        assert!(sim.validate(&rule).is_empty());
+        sim.update(&rule, &TaskPool::new());
+        cells::live_cells(&sim)
+    }
+
+    #[test]
+    fn wrap_axes_keeps_unwrapped_axes_apart() {
+        let wrapped = step_z_border_column(BoundaryMode::Wrap);
+        assert!(wrapped.iter().any(|(pos, _)| pos.z == 0));
+
+        let xy_only = step_z_border_column(BoundaryMode::WrapAxes(BVec3::new(true, true, false)));
+        assert!(!xy_only.is_empty());
+        assert!(xy_only.iter().all(|(pos, _)| pos.z != 0));
+    }
+
+    #[test]
+    fn empty_grid_step() {
+        let rule = test_rule();
+        assert!(rule.keeps_empty());
+        let mut sim = LeddooAtomic::new();
+        sim.set_bounds(IVec3::splat(32));
+
+        let tasks = TaskPool::new();
+        for generation in 1..=3 {
+            sim.update(&rule, &tasks);
+            assert_eq!(sim.generation, generation);
+            assert_eq!(sim.cell_count(), 0);
+            assert_eq!(sim.last_births, 0);
+            assert_eq!(sim.last_deaths, 0);
+        }
+        assert!(sim.validate(&rule).is_empty());
+    }
+
+    #[test]
+    fn set_cell_out_of_bounds() {
+        let rule = test_rule();
+        let mut sim = LeddooAtomic::new();
+        sim.set_bounds(IVec3::splat(32));
+
+        assert!(cells::Sim::set_cell(&mut sim, &rule, ivec3(1, 2, 3), rule.states));
+        for pos in [ivec3(-1, 0, 0), ivec3(0, 32, 0), ivec3(0, 0, 100)] {
+            assert!(!cells::Sim::set_cell(&mut sim, &rule, pos, rule.states));
+        }
+        assert_eq!(cells::live_cells(&sim), vec![(ivec3(1, 2, 3), rule.states)]);
+    }
+
+    #[test]
+    fn state_hash_of_a_seeded_run() {
+        let rule = test_rule();
+        let tasks = TaskPool::new();
+        let run = |seed| {
+            let mut sim = noise_sim(&rule, IVec3::splat(32), seed);
+            for _ in 0..50 {
+                sim.update(&rule, &tasks);
+            }
+            sim
+        };
+
+        // recorded for StdRng (chacha12) seeded with 3. changing the noise,
+        // the update or the hash layout changes these, on purpose or not.
+        let (a, b) = (run(3), run(3));
+        assert_eq!(a.state_hash(true), 0x7f30cea8999079d8);
+        assert_eq!(a.state_hash(false), 0xb71681129587ed8a);
+        assert_eq!(a.state_hash(true), b.state_hash(true));
+        assert_eq!(a.state_hash(false), b.state_hash(false));
+        assert_ne!(a.state_hash(false), a.state_hash(true));
+        assert_ne!(a.state_hash(true), run(4).state_hash(true));
+
+        // the same run on the sequential sim has to give the same cells.
+        let mut single = cells::leddoo::LeddooSingleThreaded::new();
+        single.set_bounds(IVec3::splat(32));
+        single.spawn_noise(&rule, Some(3));
+        for _ in 0..50 {
+            single.update(&rule);
+        }
+        assert_eq!(cells::first_difference(&a, &single), None);
+    }
+
+    #[test]
+    fn load_round_trip() {
+        let rule = test_rule();
+        let sim = noise_sim(&rule, IVec3::splat(32), 1);
+        let mut save = vec![];
+        sim.save_to_writer(&mut save, &rule).unwrap();
+
+        let mut loaded = LeddooAtomic::new();
+        loaded.load_from_reader(&mut &save[..], &rule).unwrap();
+        assert_eq!(loaded.cell_count(), sim.cell_count());
+        assert_eq!(cells::first_difference(&sim, &loaded), None);
+        assert!(loaded.validate(&rule).is_empty());
+    }
+
+    #[test]
+    fn load_truncated_save_keeps_the_grid() {
+        let rule = test_rule();
+        let mut sim = noise_sim(&rule, IVec3::splat(32), 1);
+        let mut save = vec![];
+        sim.save_to_writer(&mut save, &rule).unwrap();
+        let cells = sim.cell_count();
+
+        // cut in the middle of the last run.
+        assert!(sim.load_from_reader(&mut &save[..save.len() - 3], &rule).is_err());
+        assert_eq!(sim.cell_count(), cells);
+        assert!(sim.validate(&rule).is_empty());
+    }
+
+    #[test]
+    fn load_out_of_bounds_run_keeps_the_grid() {
+        let rule = test_rule();
+        let bounds = IVec3::splat(32);
+        let mut sim = noise_sim(&rule, bounds, 2);
+        let cells = sim.cell_count();
+
+        let volume = utils::volume(bounds) as u32;
+        let save = save_bytes(&rule, bounds, &[(0, 8, rule.states), (volume - 2, 8, rule.states)]);
+        assert!(sim.load_from_reader(&mut &save[..], &rule).is_err());
+        assert_eq!(sim.cell_count(), cells);
+        assert!(sim.validate(&rule).is_empty());
+
+        // an invalid value in a later run too.
+        let save = save_bytes(&rule, bounds, &[(0, 8, rule.states), (100, 8, rule.states + 1)]);
+        assert!(sim.load_from_reader(&mut &save[..], &rule).is_err());
+        assert_eq!(sim.cell_count(), cells);
+        assert!(sim.validate(&rule).is_empty());
+    }
+
+    #[test]
+    fn component_count_of_separate_blobs() {
+        let rule = test_rule();
+        let mut sim = LeddooAtomic::new();
+        sim.set_bounds(IVec3::splat(32));
+        assert_eq!(sim.component_count(&rule), 0);
+
+        // two 2x2x2 blobs, 14 cells apart.
+        for origin in [ivec3(4, 4, 4), ivec3(20, 4, 4)] {
+            for index in 0..8 {
+                sim.set_cell(origin + utils::index_to_pos(index, IVec3::splat(2)), rule.states, &rule);
+            }
+        }
+        assert_eq!(sim.component_count(&rule), 2);
+        // a line between them, touching both only diagonally. one piece for
+        // moore, three for von neumann.
+        sim.set_cell(ivec3(6, 6, 6), rule.states, &rule);
+        for x in 7..20 {
+            sim.set_cell(ivec3(x, 6, 6), rule.states, &rule);
+        }
+        assert_eq!(sim.component_count(&rule), 1);
+        let von_neumann = Rule { neighbour_method: NeighbourMethod::VonNeuman(1), ..rule.clone() };
+        assert_eq!(sim.component_count(&von_neumann), 3);
+    }
+
+    #[test]
+    fn component_count_wraps_around() {
+        let rule = test_rule();
+        let mut sim = LeddooAtomic::new();
+        sim.set_bounds(IVec3::splat(32));
+        // neighbors through the x = 0 face.
+        sim.set_cell(ivec3(0, 10, 10), rule.states, &rule);
+        sim.set_cell(ivec3(31, 10, 10), rule.states, &rule);
+
+        assert_eq!(sim.component_count(&rule), 1);
+        let dead = Rule { boundary_mode: BoundaryMode::Dead, ..rule.clone() };
+        assert_eq!(sim.component_count(&dead), 2);
+    }
+
+    #[test]
+    fn neighbour_histogram_of_a_single_cell() {
+        for rulestring in ["B4/S4/5/M", "B4/S4/5/W3,2,1"] {
+            let rule = Rule::from_rulestring(rulestring).unwrap();
+            let mut sim = LeddooAtomic::new();
+            sim.set_bounds(IVec3::splat(32));
+            sim.spawn_single(&rule);
+
+            // each offset adds its weight to one cell, the rest stay at 0.
+            let offsets: Vec<_> = rule.neighbours().collect();
+            let mut expected = vec![0; offsets.iter().map(|(_, weight)| *weight as usize).max().unwrap() + 1];
+            expected[0] = sim.total_cell_count() - offsets.len();
+            for (_, weight) in offsets {
+                expected[weight as usize] += 1;
+            }
+            assert_eq!(sim.neighbour_histogram(), expected, "{}", rulestring);
+        }
+    }
+
+    #[test]
+    fn state_distribution_adds_up() {
+        let rule = test_rule();
+        let bounds = IVec3::splat(32);
+        let volume = utils::volume(bounds);
+        let tasks = TaskPool::new();
+        let mut sim = LeddooAtomic::new();
+        sim.set_bounds(bounds);
+        assert!(sim.state_distribution().is_empty());
+
+        // a full grid is all in the full state. every cell has 26 neighbors,
+        // so one step later they're all one state lower.
+        sim.spawn_cube(&rule, bounds.x);
+        assert_eq!(sim.cell_count(), volume);
+        assert_eq!(sim.state_distribution(), [0, 0, 0, 0, volume]);
+        sim.update(&rule, &tasks);
+        assert_eq!(sim.state_distribution(), [0, 0, 0, volume]);
+
+        // a few steps of noise mix the states, still one entry per live cell.
+        let mut sim = noise_sim(&rule, bounds, 5);
+        for _ in 0..10 {
+            sim.update(&rule, &tasks);
+        }
+        assert_eq!(sim.state_distribution().iter().sum::<usize>(), sim.cell_count());
+    }
+
+    #[test]
+    fn count_where_sees_values_and_neighbors() {
+        let rule = test_rule();
+        let mut sim = LeddooAtomic::new();
+        sim.set_bounds(IVec3::splat(32));
+        sim.spawn_single(&rule);
+
+        assert_eq!(sim.count_where(|_, _| true), sim.total_cell_count());
+        assert_eq!(sim.count_where(|value, _| value == rule.states), 1);
+        assert_eq!(sim.count_where(|value, neighbors| value == 0 && neighbors == 1), 26);
+        assert_eq!(sim.count_where(|_, neighbors| neighbors > 1), 0);
+
+        let sim = noise_sim(&rule, IVec3::splat(32), 6);
+        assert_eq!(sim.count_where(|value, _| value > 0), sim.cell_count());
+    }
+
+    #[test]
+    fn export_a_single_cell() {
+        let rule = test_rule();
+        let mut sim = LeddooAtomic::new();
+        sim.set_bounds(IVec3::splat(32));
+        sim.spawn_single(&rule);
+        let orange = |_: IVec3, _: u8, _: u16| Color::rgb(1.0, 0.5, 0.0);
+
+        // one cube at the center: 8 corners, 6 quads.
+        let mut obj = vec![];
+        sim.export_obj(&mut obj, &rule, orange).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let lines: Vec<&str> = obj.lines().collect();
+        assert_eq!(lines[0], "# B4/S4/5/M");
+        assert_eq!(lines.iter().filter(|line| line.starts_with("v ")).count(), 8);
+        assert_eq!(lines.iter().filter(|line| line.starts_with("f ")).count(), 6);
+        assert_eq!(lines[1], "v -0.5 -0.5 -0.5 1 0.5 0");
+        assert_eq!(lines[9], "f 1 5 7 3");
+
+        // one point at the center, after the same header in both formats.
+        let mut binary_point = vec![0u8; 12];
+        binary_point.extend([255, 128, 0]);
+        for (format, name, point) in [
+            (PlyFormat::Ascii, "ascii", b"0 0 0 255 128 0\n".to_vec()),
+            (PlyFormat::BinaryLittleEndian, "binary_little_endian", binary_point),
+        ] {
+            let mut ply = vec![];
+            sim.export_ply(&mut ply, &rule, format, orange).unwrap();
+            let header = format!(
+                "ply\nformat {} 1.0\ncomment B4/S4/5/M\nelement vertex 1\n\
+                property float x\nproperty float y\nproperty float z\n\
+                property uchar red\nproperty uchar green\nproperty uchar blue\n\
+                end_header\n", name);
+            assert_eq!(String::from_utf8_lossy(&ply[..header.len()]), header);
+            assert_eq!(&ply[header.len()..], &point[..], "{}", name);
+        }
+    }
+
+    #[test]
+    fn wall_survives_and_counts() {
+        // a lone full cell has 0 neighbors, so S4 kills it.
+        let rule = test_rule();
+        let tasks = TaskPool::new();
+        let mut sim = LeddooAtomic::new();
+        sim.set_bounds(IVec3::splat(32));
+        let center = sim.center();
+        sim.set_wall(&rule, center, true);
+
+        for _ in 0..5 {
+            sim.update(&rule, &tasks);
+            assert_eq!(sim.get_cell(&rule, center), Some(rule.states));
+            assert_eq!(sim.neighbours_at(&rule, center + IVec3::X), Some(1));
+            assert_eq!(sim.neighbours_at(&rule, center + IVec3::ONE), Some(1));
+            assert_eq!(sim.cell_count(), 1);
+        }
+        assert!(sim.validate(&rule).is_empty());
+
+        // without the wall, it's a normal cell again.
+        sim.set_wall(&rule, center, false);
+        sim.update(&rule, &tasks);
+        assert_eq!(sim.get_cell(&rule, center), Some(rule.states - 1));
+        assert_eq!(sim.neighbours_at(&rule, center + IVec3::X), Some(0));
+    }
+
+    fn empty_sim() -> LeddooAtomic {
+        let mut sim = LeddooAtomic::new();
+        sim.set_bounds(IVec3::splat(32));
+        sim
+    }
+
+    #[test]
+    fn spawn_single_cell() {
+        let rule = test_rule();
+        let mut sim = empty_sim();
+        sim.spawn_single(&rule);
+        assert_eq!(sim.cell_count(), 1);
+        assert!(sim.validate(&rule).is_empty());
+
+        let center = sim.center();
+        assert_eq!(sim.get_cell(&rule, center), Some(rule.states));
+        assert_eq!(sim.get_cell(&rule, center + IVec3::X), Some(0));
+        assert_eq!(sim.neighbours_at(&rule, center), Some(0));
+        assert_eq!(sim.neighbours_at(&rule, center + IVec3::X), Some(1));
+        // outside the bounds goes through the boundary mode.
+        assert_eq!(sim.get_cell(&rule, center + IVec3::splat(32)), Some(rule.states));
+        let dead = Rule { boundary_mode: BoundaryMode::Dead, ..rule.clone() };
+        assert_eq!(sim.get_cell(&dead, center + IVec3::splat(32)), None);
+        assert_eq!(sim.neighbours_at(&dead, ivec3(-1, 0, 0)), None);
+    }
+
+    #[test]
+    fn spawn_cross_and_cube() {
+        let rule = test_rule();
+        let mut sim = empty_sim();
+        sim.spawn_cross(&rule, 3);
+        assert_eq!(sim.cell_count(), 1 + 6*3);
+        assert!(sim.validate(&rule).is_empty());
+
+        for (size, cells) in [(4, 64), (3, 27), (0, 1)] {
+            let mut sim = empty_sim();
+            sim.spawn_cube(&rule, size);
+            assert_eq!(sim.cell_count(), cells, "size {}", size);
+            assert!(sim.validate(&rule).is_empty());
+        }
+    }
+
+    #[test]
+    fn spawn_symmetric_is_symmetric() {
+        let rule = test_rule();
+        for symmetry in [Symmetry::MirrorX, Symmetry::Cubic, Symmetry::Octahedral] {
+            let mut sim = empty_sim();
+            sim.spawn_symmetric(&rule, 9, symmetry);
+            assert!(sim.cell_count() > 0);
+            assert!(sim.validate(&rule).is_empty());
+
+            let center = sim.center();
+            for (pos, _) in sim.live_cells() {
+                for image in symmetry.images(pos - center) {
+                    assert_eq!(sim.get_cell(&rule, center + image), Some(rule.states),
+                        "{:?}: image {} of {}", symmetry, center + image, pos);
+                }
+            }
+        }
+    }
+}
@@ -16,15 +16,18 @@ use bevy::{
 
 use crate::{
     cell_renderer::{CellRenderer},
+    cells::{self, NeighbourMismatch},
     rule::Rule,
     utils,
 };
 
+use std::time::{Duration, Instant};
+
 
 #[derive(Clone, Copy)]
 struct Cell {
     value: u8,
-    neighbors: u8,
+    neighbors: u16,
 }
 
 impl Cell {
@@ -36,24 +39,38 @@ impl Cell {
 
 pub struct LeddooSingleThreaded {
     cells: Vec<Cell>,
-    bounds: i32,
+    bounds: IVec3,
+    generation: u64,
+    // wall clock time of the last update.
+    last_step: Duration,
 }
 
 impl LeddooSingleThreaded {
     pub fn new() -> Self {
         LeddooSingleThreaded {
             cells: vec![],
-            bounds: 0,
+            bounds: IVec3::ZERO,
+            generation: 0,
+            last_step: Duration::ZERO,
         }
     }
 
-    pub fn set_bounds(&mut self, new_bounds: i32) -> i32 {
+    // survival counts with neighbour_method, there's only one counter. no
+    // margolus block updates or symmetry pass either.
+    pub fn supports(rule: &Rule) -> bool {
+        rule.with_survival_neighbours().is_none() && rule.margolus.is_none()
+            && rule.enforce_symmetry.is_none()
+    }
+
+    pub fn set_bounds(&mut self, new_bounds: IVec3) -> IVec3 {
+        let new_bounds = utils::clamp_bounds_quiet(new_bounds);
         if new_bounds != self.bounds {
             self.cells.clear();
             self.cells.resize(
-                (new_bounds*new_bounds*new_bounds) as usize,
+                utils::volume(new_bounds),
                 Cell { value: 0, neighbors: 0 });
             self.bounds = new_bounds;
+            self.generation = 0;
         }
         self.bounds
     }
@@ -77,22 +94,24 @@ impl LeddooSingleThreaded {
         utils::pos_to_index(vec, self.bounds)
     }
 
-    pub fn wrap(&self, pos: IVec3) -> IVec3 {
-        utils::wrap(pos, self.bounds)
-    }
-
-
     fn update_neighbors(&mut self, rule: &Rule, index: usize, inc: bool) {
         let pos = self.index_to_pos(index);
-        for dir in rule.neighbour_method.get_neighbour_iter() {
-            let neighbor_pos = self.wrap(pos + *dir);
+        for (dir, weight) in rule.neighbours() {
+            let neighbor_pos = match rule.boundary_mode.resolve(pos + dir, self.bounds) {
+                Some(pos) => pos,
+                None => continue,
+            };
 
             let index = self.pos_to_index(neighbor_pos);
             if inc {
-                self.cells[index].neighbors += 1;
+                self.cells[index].neighbors += weight;
             }
             else {
-                self.cells[index].neighbors -= 1;
+                // a zero count here means the bookkeeping is off somewhere
+                // else. don't take down the app in release builds.
+                let neighbors = &mut self.cells[index].neighbors;
+                debug_assert!(*neighbors >= weight, "neighbor count underflow at {}", neighbor_pos);
+                *neighbors = neighbors.saturating_sub(weight);
             }
         }
     }
@@ -105,7 +124,18 @@ impl LeddooSingleThreaded {
 
         // update values.
         for (index, cell) in self.cells.iter_mut().enumerate() {
-            if cell.is_dead() {
+            // spawns & deaths are entering & leaving the full state.
+            if let Some(transitions) = &rule.transitions {
+                let next = transitions.next(cell.value, cell.neighbors);
+                if next == rule.states && cell.value != rule.states {
+                    spawns.push(index);
+                }
+                if cell.value == rule.states && next != rule.states {
+                    deaths.push(index);
+                }
+                cell.value = next;
+            }
+            else if cell.is_dead() {
                 if rule.birth_rule.in_range(cell.neighbors) {
                     cell.value = rule.states;
                     spawns.push(index);
@@ -116,7 +146,7 @@ impl LeddooSingleThreaded {
                     if cell.value == rule.states {
                         deaths.push(index);
                     }
-                    cell.value -= 1;
+                    cell.value = rule.death_mode.next(cell.value);
                 }
             }
         }
@@ -128,43 +158,75 @@ impl LeddooSingleThreaded {
         for index in deaths {
             self.update_neighbors(rule, index, false);
         }
-    }
-
-    // TEMP: move to sims.
-    #[allow(dead_code)]
-    pub fn validate(&self, rule: &Rule) {
-        for index in 0..self.cells.len() {
-            let pos = self.index_to_pos(index);
 
-            let mut neighbors = 0;
-            for dir in rule.neighbour_method.get_neighbour_iter() {
-                let neighbor_pos = self.wrap(pos + *dir);
+        self.generation += 1;
+    }
 
-                let index = self.pos_to_index(neighbor_pos);
-                if self.cells[index].value == rule.states {
-                    neighbors += 1;
-                }
-            }
+    pub fn validate(&self, rule: &Rule) -> Vec<NeighbourMismatch> {
+        cells::neighbor_mismatches(rule, self.bounds,
+            |index| self.cells[index].value == rule.states,
+            |index| self.cells[index].neighbors)
+    }
 
-            assert_eq!(neighbors, self.cells[index].neighbors);
+    pub fn recount_neighbours(&mut self, rule: &Rule) {
+        let counts = cells::count_neighbors(rule, self.bounds,
+            |index| self.cells[index].value == rule.states);
+        for (cell, count) in self.cells.iter_mut().zip(counts) {
+            cell.neighbors = count;
         }
     }
 
-    pub fn spawn_noise(&mut self, rule: &Rule) {
-        utils::make_some_noise_default(utils::center(self.bounds), |pos| {
-            let index = self.pos_to_index(self.wrap(pos));
+    pub fn spawn_noise(&mut self, rule: &Rule, seed: Option<u64>) {
+        utils::make_some_noise_in(utils::center(self.bounds), rule.noise_radius(self.bounds), rule.seed_shape, rule.noise_kind, rule.noise_density, seed, |pos| {
+            let pos = match rule.seed_pos(pos, self.bounds) {
+                Some(pos) => pos,
+                None => return,
+            };
+            let index = self.pos_to_index(pos);
             if self.cells[index].is_dead() {
                 self.cells[index].value = rule.states;
                 self.update_neighbors(rule, index, true);
             }
         });
+
+        debug_assert_eq!(self.validate(rule), vec![]);
+    }
+
+    pub fn load_cells(&mut self, rule: &Rule, cells: &[(IVec3, u8)]) {
+        for (pos, value) in cells {
+            if !utils::is_in_bounds(*pos, self.bounds) {
+                continue;
+            }
+            let index = self.pos_to_index(*pos);
+            let old_value = self.cells[index].value;
+            let new_value = (*value).min(rule.states);
+
+            // only cells in the full state count as neighbors.
+            if old_value == rule.states && new_value != rule.states {
+                self.update_neighbors(rule, index, false);
+            }
+            if new_value == rule.states && old_value != rule.states {
+                self.update_neighbors(rule, index, true);
+            }
+            self.cells[index].value = new_value;
+        }
     }
 }
 
 
 impl crate::cells::Sim for LeddooSingleThreaded {
+    fn supports(&self, rule: &Rule) -> bool {
+        Self::supports(rule)
+    }
+
     fn update(&mut self, rule: &Rule, _task_pool: &TaskPool) {
+        let t0 = Instant::now();
         self.update(rule);
+        self.last_step = t0.elapsed();
+    }
+
+    fn last_step_millis(&self) -> f32 {
+        self.last_step.as_secs_f32() * 1000.0
     }
 
     fn render(&self, renderer: &mut CellRenderer) {
@@ -173,19 +235,37 @@ impl crate::cells::Sim for LeddooSingleThreaded {
         }
     }
 
-    fn spawn_noise(&mut self, rule: &Rule) {
-        self.spawn_noise(rule);
+    fn spawn_noise(&mut self, rule: &Rule, seed: Option<u64>) {
+        self.spawn_noise(rule, seed);
     }
 
     fn cell_count(&self) -> usize {
         self.cell_count()
     }
 
-    fn bounds(&self) -> i32 {
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn bounds(&self) -> IVec3 {
         self.bounds
     }
 
-    fn set_bounds(&mut self, new_bounds: i32) -> i32 {
+    fn set_bounds(&mut self, new_bounds: IVec3) -> IVec3 {
         self.set_bounds(new_bounds)
     }
+
+    fn validate(&self, rule: &Rule) -> Option<Vec<NeighbourMismatch>> {
+        Some(self.validate(rule))
+    }
+
+    fn recount_neighbours(&mut self, rule: &Rule) -> bool {
+        self.recount_neighbours(rule);
+        true
+    }
+
+    fn load_cells(&mut self, rule: &Rule, cells: &[(IVec3, u8)]) -> bool {
+        self.load_cells(rule, cells);
+        true
+    }
 }
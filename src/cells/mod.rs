@@ -1,28 +1,356 @@
-use bevy::{tasks::TaskPool};
-use crate::{rule::Rule, cell_renderer::CellRenderer};
+use bevy::{math::IVec3, tasks::TaskPool, prelude::{Input, KeyCode}};
+use crate::{rule::Rule, cell_renderer::CellRenderer, utils};
+
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
 
 
 pub trait Sim: Send + Sync {
     fn update(&mut self, rule: &Rule, task_pool: &TaskPool);
     fn render(&self, data: &mut CellRenderer);
 
+    // advances exactly one generation. doesn't need any bevy state besides
+    // the task pool, so it can be driven from tests & benchmarks.
+    fn step(&mut self, rule: &Rule, task_pool: &TaskPool) {
+        self.update(rule, task_pool);
+    }
+
+    // steps as many generations as fit in `budget`, see step_for.
+    fn step_for(&mut self, rule: &Rule, task_pool: &TaskPool, budget: Duration) -> u32 {
+        step_for(budget, || self.step(rule, task_pool))
+    }
+
+    // false if the sim would ignore part of `rule` (eg: a separate survival
+    // neighbourhood) and so run a different rule. Sims doesn't switch to a
+    // sim that can't run the rule. leddoo's atomic sim runs everything.
+    fn supports(&self, _rule: &Rule) -> bool {
+        true
+    }
+
+    // `input` has no keys pressed while egui wants the keyboard (a text field
+    // has focus), see Sims' update. so implementations can use single keys.
+    fn handle_input(&mut self, input: &Input<KeyCode>, rule: &Rule, seed: Option<u64>) {
+        if input.just_pressed(KeyCode::P) {
+            self.spawn_noise(rule, seed);
+        }
+    }
+
     fn reset(&mut self) {
         let bounds = self.bounds();
-        self.set_bounds(0);
+        self.set_bounds(IVec3::ZERO);
         self.set_bounds(bounds);
     }
 
-    fn spawn_noise(&mut self, rule: &Rule);
+    // `None` spawns different noise every time.
+    fn spawn_noise(&mut self, rule: &Rule, seed: Option<u64>);
 
     fn cell_count(&self) -> usize;
 
-    fn bounds(&self) -> i32;
-    fn set_bounds(&mut self, new_bounds: i32) -> i32;
+    fn is_empty(&self) -> bool {
+        self.cell_count() == 0
+    }
+
+    // live cells / total cells.
+    fn fill_ratio(&self) -> f32 {
+        self.cell_count() as f32 / utils::volume(self.bounds()).max(1) as f32
+    }
+
+    // number of updates since the last reset.
+    fn generation(&self) -> u64;
+
+    // wall clock time of the last update. 0 before the first one.
+    fn last_step_millis(&self) -> f32 {
+        0.0
+    }
+
+    // true if the sim is known to have reached a still life or a short
+    // oscillator. sims that don't track this are never stable.
+    fn is_stable(&self) -> bool {
+        false
+    }
+
+    // undoes the last update. false if the sim can't (any further).
+    fn step_back(&mut self) -> bool {
+        false
+    }
+
+    // how many updates step_back can undo. keeping them costs a copy of the
+    // live cells per update, so 0 unless stepping back is wanted. sims
+    // without a history ignore this.
+    fn set_history_length(&mut self, _length: usize) {}
+
+    // keep the generation each cell was born in, and render it as
+    // CellRenderer::ages. costs memory, so only while a color method needs
+    // it. sims that can't ignore this.
+    fn set_track_births(&mut self, _track: bool) {}
+
+    // resets to the cells it was seeded with, for another run with the same
+    // start. false if the sim doesn't keep them.
+    fn reset_to_initial(&mut self, _rule: &Rule) -> bool {
+        false
+    }
+
+    // cells that entered/left the full state in the last update. both are
+    // zero for sims that don't track them.
+    fn last_births(&self) -> usize {
+        0
+    }
+    fn last_deaths(&self) -> usize {
+        0
+    }
+
+    fn bounds(&self) -> IVec3;
+    fn set_bounds(&mut self, new_bounds: IVec3) -> IVec3;
+
+    // keeps the live cells that are still in bounds. sims that can't do that
+    // just reset.
+    fn resize_preserving(&mut self, _rule: &Rule, new_bounds: IVec3) -> IVec3 {
+        self.set_bounds(new_bounds)
+    }
+
+    // compares the stored neighbor counts with a recount. None if the sim
+    // doesn't store (all of) them.
+    fn validate(&self, _rule: &Rule) -> Option<Vec<NeighbourMismatch>> {
+        None
+    }
+
+    // one cell, like load_cells. false if the sim can't, or pos is out of
+    // bounds.
+    fn set_cell(&mut self, rule: &Rule, pos: IVec3, value: u8) -> bool {
+        utils::is_in_bounds(pos, self.bounds()) && self.load_cells(rule, &[(pos, value)])
+    }
+
+    // replaces the stored neighbor counts with a recount, eg: after cells
+    // were set from outside. false if the sim doesn't store them.
+    fn recount_neighbours(&mut self, _rule: &Rule) -> bool {
+        false
+    }
+
+    // sets the cells at these (absolute) positions, overwriting what's there.
+    // positions outside the bounds are skipped. false if the sim can't.
+    fn load_cells(&mut self, _rule: &Rule, _cells: &[(IVec3, u8)]) -> bool {
+        false
+    }
+
+    // the cells in leddoo's atomic save format, see save_to_writer there.
+    // the error says so if the sim can't.
+    fn save_to_writer(&self, _w: &mut dyn Write, _rule: &Rule) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "this sim can't save its cells"))
+    }
+
+    // replaces the cells (and bounds) with a save. on an error the sim keeps
+    // its cells.
+    fn load_from_reader(&mut self, _r: &mut dyn Read, _rule: &Rule) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "this sim can't load saves"))
+    }
+}
+
+
+// calls `step` until the next call would likely run past `budget`, going by
+// the slowest step so far. always at least once, so a budget shorter than a
+// step still makes progress. returns the number of calls.
+pub fn step_for<F: FnMut()>(budget: Duration, mut step: F) -> u32 {
+    let t0 = Instant::now();
+    let mut steps = 0;
+    let mut slowest = Duration::ZERO;
+    loop {
+        let t1 = Instant::now();
+        step();
+        steps += 1;
+        slowest = slowest.max(t1.elapsed());
+        if t0.elapsed() + slowest > budget {
+            return steps;
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NeighbourMismatch {
+    pub pos: IVec3,
+    pub expected: u16,
+    pub actual: u16,
+}
+
+// the neighbor counts from scratch. counts outwards from the full cells, like
+// the sims do. (with Clamp, counting inwards from each cell gives different
+// results.)
+pub fn count_neighbors<F: Fn(usize) -> bool>(rule: &Rule, bounds: IVec3, is_full: F) -> Vec<u16> {
+    let mut neighbors = vec![0u16; utils::volume(bounds)];
+    for index in 0..neighbors.len() {
+        if !is_full(index) {
+            continue;
+        }
+
+        let pos = utils::index_to_pos(index, bounds);
+        for (dir, weight) in rule.neighbours() {
+            if let Some(neighbor_pos) = rule.boundary_mode.resolve(pos + dir, bounds) {
+                neighbors[utils::pos_to_index(neighbor_pos, bounds)] += weight;
+            }
+        }
+    }
+    neighbors
+}
+
+// every cell where `actual` disagrees with count_neighbors.
+pub fn neighbor_mismatches<F, G>(rule: &Rule, bounds: IVec3, is_full: F, actual: G) -> Vec<NeighbourMismatch>
+    where F: Fn(usize) -> bool, G: Fn(usize) -> u16
+{
+    count_neighbors(rule, bounds, is_full).into_iter()
+        .enumerate()
+        .filter(|(index, expected)| actual(*index) != *expected)
+        .map(|(index, expected)| NeighbourMismatch {
+            pos: utils::index_to_pos(index, bounds),
+            expected,
+            actual: actual(index),
+        })
+        .collect()
+}
+
+
+// the backends that can take over each other's cells, see Sims::switch_backend.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SimBackend {
+    Single,
+    Atomic,
+    Sparse,
+    // only for binary rules, so not in ALL. Sims switches to it on its own.
+    Bitset,
+}
+
+impl SimBackend {
+    pub const ALL: [SimBackend; 3] = [SimBackend::Single, SimBackend::Atomic, SimBackend::Sparse];
+
+    pub fn build(&self) -> Box<dyn Sim> {
+        match self {
+            SimBackend::Single => Box::new(leddoo::LeddooSingleThreaded::new()),
+            SimBackend::Atomic => Box::new(leddoo::LeddooAtomic::new()),
+            SimBackend::Sparse => Box::new(sparse::SparseSim::new()),
+            SimBackend::Bitset => Box::new(bitset::BitsetSim::new()),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SimBackend::Single => "leddoo single-threaded",
+            SimBackend::Atomic => "leddoo atomic",
+            SimBackend::Sparse => "sparse",
+            SimBackend::Bitset => "bitset",
+        }
+    }
+}
+
+
+// positions & values of the live cells of any sim, through render.
+pub fn live_cells(sim: &dyn Sim) -> Vec<(IVec3, u8)> {
+    let mut renderer = CellRenderer::new();
+    renderer.set_bounds(sim.bounds());
+    sim.render(&mut renderer);
+    (0..renderer.cell_count())
+        .filter(|index| renderer.values[*index] != 0)
+        .map(|index| (utils::index_to_pos(index, renderer.bounds), renderer.values[index]))
+        .collect()
+}
+
+
+// returns the first cell where the two sims disagree (value or neighbors).
+// for debugging the parallel sims against a sequential one, eg: leddoo atomic
+// vs leddoo single-threaded (same logic, no tasks), stepped with the same seed.
+// both sims need the same bounds.
+// note: tantan's sims don't keep neighbor counts and the sparse sim only renders
+// them for live cells, so only compare those with care.
+pub fn first_difference(a: &dyn Sim, b: &dyn Sim) -> Option<IVec3> {
+    assert_eq!(a.bounds(), b.bounds());
+
+    let render = |sim: &dyn Sim| {
+        let mut renderer = CellRenderer::new();
+        renderer.set_bounds(sim.bounds());
+        sim.render(&mut renderer);
+        renderer
+    };
+    let (a, b) = (render(a), render(b));
+
+    (0..a.cell_count())
+        .find(|index| {
+            a.values[*index] != b.values[*index] ||
+            a.neighbors[*index] != b.neighbors[*index]
+        })
+        .map(|index| utils::index_to_pos(index, a.bounds))
+}
+
+
+pub struct BenchResult {
+    pub name: String,
+    pub steps_per_second: f64,
+    pub cell_count: usize,
 }
 
+// steps every sim `steps` times from the same seeded noise. the backends
+// implement the same rule, so they must end up with the same cell count.
+// the atomic sim rounds its bounds up to multiples of 32, so use multiples
+// of 32 to compare it to the others.
+pub fn benchmark(
+    sims: &mut [(String, Box<dyn Sim>)],
+    rule: &Rule, bounds: IVec3, seed: u64, steps: u32,
+    task_pool: &TaskPool,
+) -> Vec<BenchResult> {
+    let results: Vec<BenchResult> = sims.iter_mut()
+        .map(|(name, sim)| {
+            sim.set_bounds(bounds);
+            sim.reset();
+            sim.spawn_noise(rule, Some(seed));
+
+            let t0 = std::time::Instant::now();
+            for _ in 0..steps {
+                sim.step(rule, task_pool);
+            }
+            let elapsed = t0.elapsed().as_secs_f64();
+
+            BenchResult {
+                name: name.clone(),
+                steps_per_second: steps as f64 / elapsed.max(f64::EPSILON),
+                cell_count: sim.cell_count(),
+            }
+        })
+        .collect();
+
+    for result in results.iter() {
+        assert_eq!(result.cell_count, results[0].cell_count,
+            "{} disagrees with {}", result.name, results[0].name);
+    }
+    results
+}
 
 pub mod sims;
 pub use sims::*;
 
 pub mod tantan;
 pub mod leddoo;
+pub mod sparse;
+pub mod bitset;
+pub mod composite;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::math::ivec3;
+
+    #[test]
+    fn set_bounds_is_at_least_one() {
+        let mut sims: Vec<(&str, Box<dyn Sim>)> = SimBackend::ALL.iter()
+            .chain(&[SimBackend::Bitset])
+            .map(|backend| (backend.name(), backend.build()))
+            .collect();
+        sims.push(("tantan single-threaded", Box::new(tantan::CellsSinglethreaded::new())));
+        sims.push(("tantan multi-threaded", Box::new(tantan::CellsMultithreaded::new())));
+        sims.push(("composite", Box::new(composite::CompositeSim::new(vec![]))));
+
+        for (name, mut sim) in sims {
+            for bounds in [IVec3::ZERO, ivec3(0, 5, -3)] {
+                let result = sim.set_bounds(bounds);
+                assert!(result.cmpge(IVec3::ONE).all(), "{}: {} for {}", name, result, bounds);
+                assert_eq!(sim.bounds(), result, "{}", name);
+            }
+        }
+    }
+}
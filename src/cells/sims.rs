@@ -1,16 +1,52 @@
 use bevy::{
-    prelude::{Plugin, Res, ResMut, Query, Color},
+    math::{ivec3, BVec3, IVec3, Vec2, Vec3},
+    app::AppExit,
+    diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
+    prelude::{CoreStage, Plugin, Res, ResMut, Query, With, GlobalTransform, Color, Input, KeyCode, EventReader, EventWriter, Time, Camera, MouseButton, Windows},
     tasks::AsyncComputeTaskPool,
 };
 use bevy_egui:: {egui, EguiContext};
 use crate::{
-    cells::Sim,
-    rule::{Rule, ColorMethod},
-    neighbours::NeighbourMethod,
-    cell_renderer::{InstanceMaterialData, InstanceData, CellRenderer},
-    utils,
+    cell_event::{GridFilledEvent, ScreenshotEvent},
+    rotating_camera::{FrameTarget, RotatingCamera},
+    cells::{self, bitset::BitsetSim, Sim, SimBackend},
+    stats::RunSummary,
+    recording::{RecordError, RecordSession},
+    search::{RuleSearch, SearchSettings},
+    rule::{Rule, RuleLibrary, ColorMethod, BoundaryMode, DeathMode, Symmetry},
+    palette::Palette,
+    patterns,
+    neighbours::{NeighbourMethod, MAX_RADIUS},
+    cell_renderer::{InstanceMaterialData, InstanceData, CellRenderer, Translucent},
+    utils::{self, NoiseKind, SeedShape},
 };
 
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+
+// size & alpha of a ghost that just died, both fade to zero.
+const GHOST_SCALE: f32 = 0.4;
+const GHOST_ALPHA: f32 = 0.3;
+// same for the dead cells on the slice, see slice_dead_color.
+const SLICE_DEAD_SCALE: f32 = 0.9;
+const SLICE_DEAD_ALPHA: f32 = 0.06;
+
+// catch up limit for steps_per_second.
+const MAX_STEPS_PER_FRAME: u32 = 8;
+
+// time spent stepping per frame while fast forwarding (holding f). leaves
+// some of a 60 fps frame for rendering.
+const FAST_FORWARD_BUDGET: std::time::Duration = std::time::Duration::from_millis(12);
+
+// generations in the hud's step time graph, and its size in points.
+const STEP_GRAPH_LENGTH: usize = 300;
+const STEP_GRAPH_SIZE: [f32; 2] = [200.0, 60.0];
+
+// generations step back can undo, once the history is on.
+const STEP_BACK_HISTORY: usize = 8;
+
 
 #[derive(Clone)]
 pub struct Example {
@@ -24,8 +60,59 @@ pub struct Example {
 pub struct Sims {
     sims: Vec<(String, Box<dyn Sim>)>,
     active_sim: usize,
-    bounds: i32,
+    bounds: IVec3,
     update_dt: std::time::Duration,
+    seed: Option<u64>,
+    pause_when_stable: bool,
+    paused: bool,
+    fill_threshold: f32,
+    filled: bool,
+    keep_cells_on_resize: bool,
+    // rule changes restart from the cells of the last start, see
+    // Sim::reset_to_initial, instead of new noise.
+    same_start_on_rule_change: bool,
+    // binary rules run on the bitset sim while the atomic sim is active.
+    auto_bitset: bool,
+    auto_frame: bool,
+    // the numbers overlay, toggled with f3.
+    show_hud: bool,
+    // last_step_millis of the last STEP_GRAPH_LENGTH generations, for the
+    // hud. sampled once per generation, whether the hud is shown or not.
+    step_times: VecDeque<f32>,
+    step_times_generation: u64,
+    // left click toggles cells, see pick_cells.
+    edit_on_click: bool,
+    // colors above 1.0 are passed on (for bloom), instead of clamped.
+    hdr: bool,
+    // dying cells get value/states as their alpha.
+    fade_dying: bool,
+    // dead cells stay visible (small & faint) for this many generations.
+    // 0 disables the trails.
+    ghost_ttl: u8,
+    // per cell, the generations its ghost has left.
+    ghosts: Vec<u8>,
+    screenshot_interval: Option<u64>,
+    // recount the neighbors after every step (f9), and report mismatches.
+    validate_steps: bool,
+    // mismatches after the last validated step, None if it couldn't be checked.
+    mismatches: Option<usize>,
+    // only render cells with this z, moved with the up/down arrow keys.
+    slice: Option<i32>,
+    // the dead cells on the slice are drawn faintly in this color, so the
+    // lattice shows. None hides them like everywhere else.
+    slice_dead_color: Option<Color>,
+    // None steps once per frame, so the speed depends on the frame rate.
+    steps_per_second: Option<f32>,
+    // steps that are due, but haven't been made yet.
+    step_accumulator: f32,
+    // the sims keep STEP_BACK_HISTORY generations for step back. off by
+    // default, it costs a copy of the live cells per step.
+    keep_history: bool,
+    // toggled with r, one frame per step.
+    recording: Option<RecordSession>,
+    record_fps: u16,
+    // stream the frames to RECORD_SPILL_PATH instead of keeping them in memory.
+    record_to_disk: bool,
 
     renderer: Option<Box<CellRenderer>>, // rust...
 
@@ -33,8 +120,28 @@ pub struct Sims {
     color_method: ColorMethod,
     color1: Color,
     color2: Color,
+    rulestring: String,
+    rulestring_error: Option<String>,
+    // a .gpl or hex list, see palette.rs.
+    palette_path: String,
+    palette_error: Option<String>,
+    // see patterns::from_ascii.
+    ascii_pattern: String,
+    ascii_error: Option<String>,
+    // the last thing the user should know about (a refused sim switch, an
+    // action the sim can't do), shown under the simulator.
+    status: Option<String>,
+    // the same from tasks (eg: a saved screenshot), update moves it to status.
+    task_status: Arc<Mutex<Option<String>>>,
+
+    // the annealing search in the rule section, see search.rs.
+    search: Option<RuleSearch>,
+    search_settings: SearchSettings,
 
     examples: Vec<Example>,
+    library: RuleLibrary,
+    // the library rule that was applied last, if any.
+    library_index: Option<usize>,
 }
 
 impl Sims {
@@ -42,14 +149,54 @@ impl Sims {
         Sims {
             sims: vec![],
             active_sim: usize::MAX,
-            bounds: 64,
+            bounds: IVec3::splat(64),
             update_dt: std::time::Duration::from_secs(0),
+            seed: None,
+            pause_when_stable: false,
+            paused: false,
+            fill_threshold: 0.9,
+            filled: false,
+            keep_cells_on_resize: false,
+            same_start_on_rule_change: false,
+            auto_bitset: true,
+            auto_frame: false,
+            show_hud: false,
+            step_times: VecDeque::with_capacity(STEP_GRAPH_LENGTH),
+            step_times_generation: 0,
+            edit_on_click: false,
+            hdr: false,
+            fade_dying: false,
+            ghost_ttl: 0,
+            ghosts: vec![],
+            screenshot_interval: None,
+            validate_steps: false,
+            mismatches: None,
+            slice: None,
+            slice_dead_color: None,
+            steps_per_second: None,
+            step_accumulator: 0.0,
+            keep_history: false,
+            recording: None,
+            record_fps: 15,
+            record_to_disk: false,
             renderer: Some(Box::new(CellRenderer::new())),
             rule: None,
             color_method: ColorMethod::DistToCenter,
             color1: Color::YELLOW,
             color2: Color::RED,
+            rulestring: String::new(),
+            rulestring_error: None,
+            palette_path: String::new(),
+            palette_error: None,
+            ascii_pattern: "##\n##\n\n##\n##".into(),
+            ascii_error: None,
+            status: None,
+            task_status: Arc::new(Mutex::new(None)),
+            search: None,
+            search_settings: SearchSettings::default(),
             examples: vec![],
+            library: RuleLibrary::empty(),
+            library_index: None,
         }
     }
 
@@ -57,25 +204,143 @@ impl Sims {
         self.sims.push((name, sim));
     }
 
-    pub fn add_example(&mut self, example: Example) {
+    pub fn add_example(&mut self, example: Example) -> usize {
         self.examples.push(example);
+        self.examples.len() - 1
     }
 
+    // only before the first update, the bounds are applied in set_sim.
+    pub fn set_bounds(&mut self, bounds: IVec3) {
+        self.bounds = utils::clamp_bounds(bounds);
+    }
+
+    // false (and the status says why) if this sim would ignore part of the
+    // rule, see Sim::supports.
+    fn can_run(&mut self, index: usize, rule: &Rule) -> bool {
+        let (name, sim) = &self.sims[index];
+        if sim.supports(rule) {
+            return true;
+        }
+        self.status = Some(format!("{} can't run {}, it would ignore part of the rule",
+            name, rule.to_rulestring()));
+        false
+    }
+
+    // stays on the current sim if the new one can't run the rule.
     pub fn set_sim(&mut self, index: usize) {
+        let rule = self.rule.take().unwrap();
+        if self.active_sim < self.sims.len() && !self.can_run(index, &rule) {
+            self.rule = Some(rule);
+            return;
+        }
         if self.active_sim < self.sims.len() {
             self.sims[self.active_sim].1.reset();
         }
 
-        let rule = self.rule.take().unwrap();
         self.active_sim = index;
         self.bounds = self.sims[index].1.set_bounds(self.bounds);
-        self.sims[index].1.spawn_noise(&rule);
+        self.sims[index].1.spawn_noise(&rule, self.seed);
         self.renderer.as_mut().unwrap().set_bounds(self.bounds);
         self.rule = Some(rule);
     }
 
+    // like set_sim, but the backend's sim (added if it isn't in the list yet)
+    // starts with the current cells instead of noise. the generation restarts
+    // at 0.
+    pub fn switch_backend(&mut self, backend: SimBackend) {
+        let rule = self.rule.take().unwrap();
+        let live = cells::live_cells(self.sims[self.active_sim].1.as_ref());
+
+        let index = match self.sims.iter().position(|(name, _)| name == backend.name()) {
+            Some(index) => index,
+            None => {
+                self.sims.push((backend.name().into(), backend.build()));
+                self.sims.len() - 1
+            }
+        };
+        if !self.can_run(index, &rule) {
+            self.rule = Some(rule);
+            return;
+        }
+        self.sims[self.active_sim].1.reset();
+        self.active_sim = index;
+
+        let sim = &mut self.sims[index].1;
+        self.bounds = sim.set_bounds(self.bounds);
+        sim.reset();
+        if sim.load_cells(&rule, &live) {
+            sim.recount_neighbours(&rule);
+        }
+        else {
+            sim.spawn_noise(&rule, self.seed);
+        }
+        self.renderer.as_mut().unwrap().set_bounds(self.bounds);
+        self.rule = Some(rule);
+    }
+
+    // the active sim's cells, see Sim::save_to_writer. the result goes to
+    // the status.
+    pub fn save_cells(&mut self, path: &str) {
+        let rule = self.rule.as_ref().unwrap();
+        let sim = &self.sims[self.active_sim].1;
+        let result = std::fs::File::create(path).and_then(|file| {
+            let mut writer = io::BufWriter::new(file);
+            sim.save_to_writer(&mut writer, rule)?;
+            writer.flush()
+        });
+        self.status = Some(match result {
+            Ok(()) => format!("saved generation {} to {}", sim.generation(), path),
+            Err(error) => format!("couldn't save {}: {}", path, error),
+        });
+    }
+
+    // the save's bounds replace the current ones.
+    pub fn load_cells(&mut self, path: &str) {
+        let rule = self.rule.as_ref().unwrap();
+        let sim = &mut self.sims[self.active_sim].1;
+        let result = std::fs::File::open(path)
+            .and_then(|file| sim.load_from_reader(&mut io::BufReader::new(file), rule));
+        self.status = Some(match result {
+            Ok(()) => {
+                self.bounds = sim.bounds();
+                self.renderer.as_mut().unwrap().set_bounds(self.bounds);
+                format!("loaded {}", path)
+            }
+            Err(error) => format!("couldn't load {}: {}", path, error),
+        });
+    }
+
     pub fn set_example(&mut self, index: usize) {
         let example = self.examples[index].clone();
+        self.apply_example(example);
+    }
+
+    pub fn set_library(&mut self, library: RuleLibrary) {
+        self.library = library;
+        self.library_index = None;
+    }
+
+    // like an example, but the color method is only changed if the file has
+    // one, and its bounding size is applied.
+    pub fn set_library_rule(&mut self, index: usize) {
+        let (name, file) = &self.library.entries[index];
+        let example = Example {
+            name: name.clone(),
+            rule: file.rule.clone(),
+            color_method: file.color_method.clone().unwrap_or_else(|| self.color_method.clone()),
+            color1: self.color1,
+            color2: self.color2,
+        };
+        if let (Some(size), true) = (file.bounding_size, self.active_sim < self.sims.len()) {
+            let bounds = utils::clamp_bounds(IVec3::splat(size));
+            self.bounds = self.sims[self.active_sim].1.set_bounds(bounds);
+            self.renderer.as_mut().unwrap().set_bounds(self.bounds);
+        }
+        self.library_index = Some(index);
+        self.apply_example(example);
+    }
+
+    fn apply_example(&mut self, example: Example) {
         let rule = example.rule;
         self.color_method = example.color_method;
         self.color1 = example.color1;
@@ -84,7 +349,7 @@ impl Sims {
         if self.active_sim < self.sims.len() {
             let sim = &mut self.sims[self.active_sim].1;
             sim.reset();
-            sim.spawn_noise(&rule);
+            sim.spawn_noise(&rule, self.seed);
         }
         self.rule = Some(rule);
     }
@@ -93,16 +358,69 @@ impl Sims {
 
 pub fn update(
     mut this: ResMut<Sims>,
-    mut query: Query<&mut InstanceMaterialData>,
+    mut query: Query<(&mut InstanceMaterialData, Option<&Translucent>)>,
+    camera: Query<&GlobalTransform, With<RotatingCamera>>,
     task_pool: Res<AsyncComputeTaskPool>,
-    mut egui_context: ResMut<EguiContext>
+    keys: Res<Input<KeyCode>>,
+    mut filled_events: EventWriter<GridFilledEvent>,
+    mut frame_target: ResMut<FrameTarget>,
+    mut screenshot_events: EventWriter<ScreenshotEvent>,
+    mut egui_context: ResMut<EguiContext>,
+    time: Res<Time>,
 ) {
+    // field by field from here, so the active sim can stay borrowed while
+    // the other fields change.
+    let this = &mut *this;
     if this.active_sim > this.sims.len() {
         this.set_sim(0);
     }
 
+    // while a text field has focus (rulestring, palette path, ascii
+    // pattern), the typed letters are text, not shortcuts. so everything
+    // below sees no keys at all.
+    let no_keys = Input::<KeyCode>::default();
+    let keys: &Input<KeyCode> =
+        if egui_context.ctx_mut().wants_keyboard_input() { &no_keys }
+        else { &keys };
+
+    // the digits alone are camera bookmarks, see rotating_camera.rs.
+    let ctrl = keys.pressed(KeyCode::LControl) || keys.pressed(KeyCode::RControl);
+    let backend_keys = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3];
+    for (key, backend) in backend_keys.into_iter().zip(SimBackend::ALL) {
+        if ctrl && keys.just_pressed(key) {
+            this.switch_backend(backend);
+        }
+    }
+
+    // page down goes to the next rule, wrapping around at both ends.
+    let library_len = this.library.entries.len();
+    if library_len > 0 {
+        let step =
+            if keys.just_pressed(KeyCode::PageDown) { Some(1) }
+            else if keys.just_pressed(KeyCode::PageUp) { Some(library_len - 1) }
+            else { None };
+        if let Some(step) = step {
+            let index = match this.library_index {
+                Some(index) => (index + step) % library_len,
+                None => if step == 1 { 0 } else { library_len - 1 },
+            };
+            this.set_library_rule(index);
+        }
+    }
+
     let mut bounds = this.bounds;
     let mut active_sim = this.active_sim;
+    let mut step_once = false;
+    let mut step_back = false;
+    let mut restart = false;
+    let mut toggle_recording = false;
+    let mut randomize_rule = false;
+    let mut save_cells = false;
+    let mut load_cells = false;
+
+    if let Some(status) = this.task_status.lock().unwrap().take() {
+        this.status = Some(status);
+    }
 
     egui::Window::new("Celluar!").show(egui_context.ctx_mut(), |ui| {
         let old_bounds = bounds;
@@ -119,32 +437,239 @@ pub fn update(
 
             if active_sim != old_active {
                 this.set_sim(active_sim);
+                active_sim = this.active_sim;
                 bounds = this.bounds; // i don't like it.
             }
+            if let Some(status) = &this.status {
+                ui.label(status.as_str());
+            }
+            ui.label("ctrl + 1/2/3: single/atomic/sparse, keeping the cells");
+            ui.label("1..9: camera bookmarks, shift to store");
+            ui.label("o: orthographic/perspective");
+            ui.label("home: restart from the same cells");
+            ui.checkbox(&mut this.auto_bitset, "bitset sim for binary rules");
 
             let update_dt = this.update_dt;
+            let mut seed = this.seed;
+            let mut pause_when_stable = this.pause_when_stable;
+            let mut paused = this.paused;
+            let mut fill_threshold = this.fill_threshold;
+            let mut keep_cells_on_resize = this.keep_cells_on_resize;
+            let mut ascii_pattern = std::mem::take(&mut this.ascii_pattern);
+            let mut ascii_error = this.ascii_error.take();
             let rule = this.rule.take().unwrap();
             let sim = &mut this.sims[active_sim].1;
 
             let cell_count = sim.cell_count();
-            ui.label(format!("cells: {}", cell_count));
+            ui.label(format!("generation: {}", sim.generation()));
+            ui.label(format!("cells: {} ({:.1}% full)", cell_count, 100.0*sim.fill_ratio()));
+            ui.label(format!("births: {}, deaths: {}", sim.last_births(), sim.last_deaths()));
             ui.label(format!("update: {:.2?} per cell", update_dt / cell_count.max(1) as u32));
+            if sim.is_stable() {
+                ui.label("stable");
+            }
 
             if ui.button("reset").clicked() {
                 sim.reset();
             }
             if ui.button("spawn noise").clicked() {
-                sim.spawn_noise(&rule);
+                sim.spawn_noise(&rule, seed);
             }
+            ui.horizontal(|ui| {
+                if ui.button("save cells").clicked() {
+                    save_cells = true;
+                }
+                if ui.button("load cells").clicked() {
+                    load_cells = true;
+                }
+                ui.label(CELLS_SAVE_PATH);
+            });
+
+            egui::CollapsingHeader::new("ascii pattern").show(ui, |ui| {
+                ui.text_edit_multiline(&mut ascii_pattern);
+                if ui.button("stamp at center").clicked() {
+                    let cells = patterns::from_ascii(&ascii_pattern);
+                    ascii_error = match patterns::centered_origin(&cells, bounds) {
+                        Ok(origin) => {
+                            let cells: Vec<(IVec3, u8)> = cells.into_iter()
+                                .map(|(pos, value)| (origin + pos, value.min(rule.states)))
+                                .collect();
+                            // load_cells keeps the neighbor counts up to date.
+                            if sim.load_cells(&rule, &cells) { None }
+                            else { Some("this sim can't load cells".into()) }
+                        }
+                        Err(error) => Some(error.to_string()),
+                    };
+                }
+                if let Some(error) = &ascii_error {
+                    ui.label(error.as_str());
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let mut fixed_seed = seed.is_some();
+                ui.checkbox(&mut fixed_seed, "fixed seed");
+                if fixed_seed {
+                    let mut value = seed.unwrap_or(0);
+                    ui.add(egui::DragValue::new(&mut value));
+                    seed = Some(value);
+                }
+                else {
+                    seed = None;
+                }
+            });
 
-            ui.add(egui::Slider::new(&mut bounds, 32..=128)
+            // sets all axes at once, shows the largest one.
+            let mut size = bounds.max_element();
+            ui.add(egui::Slider::new(&mut size, 32..=128)
                 .text("bounding size"));
+            if size != old_bounds.max_element() {
+                bounds = IVec3::splat(size);
+            }
+
+            egui::CollapsingHeader::new("per axis bounds").show(ui, |ui| {
+                ui.add(egui::Slider::new(&mut bounds.x, 8..=128).text("x"));
+                ui.add(egui::Slider::new(&mut bounds.y, 8..=128).text("y"));
+                ui.add(egui::Slider::new(&mut bounds.z, 8..=128).text("z"));
+            });
+
+            ui.checkbox(&mut keep_cells_on_resize, "keep cells when resizing");
+
             if bounds != old_bounds {
-                bounds = sim.set_bounds(bounds);
-                sim.spawn_noise(&rule);
+                if keep_cells_on_resize {
+                    bounds = sim.resize_preserving(&rule, bounds);
+                }
+                else {
+                    bounds = sim.set_bounds(bounds);
+                    sim.spawn_noise(&rule, seed);
+                }
                 this.renderer.as_mut().unwrap().set_bounds(bounds);
             }
 
+            ui.checkbox(&mut pause_when_stable, "pause when stable");
+            ui.horizontal(|ui| {
+                if ui.button("restart (home)").clicked() {
+                    restart = true;
+                }
+                ui.checkbox(&mut this.same_start_on_rule_change, "same start on rule change");
+            });
+
+            if ui.button("recount neighbors").clicked() && !sim.recount_neighbours(&rule) {
+                this.status = Some("this sim doesn't store neighbor counts".into());
+            }
+
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut this.validate_steps, "validate neighbors (f9)");
+                if this.validate_steps {
+                    match this.mismatches {
+                        Some(0) => ui.label("ok"),
+                        Some(count) => ui.label(format!("{} mismatches", count)),
+                        None => ui.label("not supported"),
+                    };
+                }
+            });
+            ui.checkbox(&mut this.auto_frame, "auto frame camera");
+            ui.checkbox(&mut this.show_hud, "info overlay (f3)");
+            ui.checkbox(&mut this.edit_on_click, "click to toggle cells (on the slice, if any)");
+            ui.checkbox(&mut this.hdr, "hdr colors (for bloom)");
+            ui.add(egui::Slider::new(&mut this.ghost_ttl, 0..=20).text("ghost trail"));
+            if this.color_method.is_hdr() && !this.hdr {
+                ui.label("the color method needs hdr colors to glow");
+            }
+
+            ui.horizontal(|ui| {
+                let mut slice = this.slice;
+                let mut enabled = slice.is_some();
+                ui.checkbox(&mut enabled, "slice");
+                if enabled {
+                    let mut z = slice.unwrap_or(bounds.z / 2);
+                    ui.add(egui::Slider::new(&mut z, 0..=bounds.z - 1).text("z"));
+                    slice = Some(z);
+                }
+                else {
+                    slice = None;
+                }
+                this.slice = slice;
+            });
+            if this.slice.is_some() {
+                ui.horizontal(|ui| {
+                    let mut color = this.slice_dead_color;
+                    let mut enabled = color.is_some();
+                    ui.checkbox(&mut enabled, "show dead cells on the slice");
+                    if enabled {
+                        let mut value = color.unwrap_or(Color::GRAY);
+                        color_picker(ui, &mut value);
+                        color = Some(value);
+                    }
+                    else {
+                        color = None;
+                    }
+                    this.slice_dead_color = color;
+                });
+            }
+
+            ui.horizontal(|ui| {
+                let mut interval = this.screenshot_interval;
+                let mut enabled = interval.is_some();
+                ui.checkbox(&mut enabled, "screenshot every");
+                if enabled {
+                    let mut value = interval.unwrap_or(10);
+                    ui.add(egui::DragValue::new(&mut value).clamp_range(1..=10000));
+                    interval = Some(value);
+                }
+                else {
+                    interval = None;
+                }
+                this.screenshot_interval = interval;
+            });
+
+            ui.horizontal(|ui| {
+                let label = match &this.recording {
+                    Some(recording) => format!("stop ({} frames) (r)", recording.frame_count()),
+                    None => "record (r)".into(),
+                };
+                if ui.button(label).clicked() {
+                    toggle_recording = true;
+                }
+                // both only apply to the next recording.
+                ui.add(egui::DragValue::new(&mut this.record_fps).clamp_range(1..=60).suffix(" fps"));
+                ui.checkbox(&mut this.record_to_disk, "stream to disk");
+            });
+
+            ui.add(egui::Slider::new(&mut fill_threshold, 0.0..=1.0)
+                .text("fill warning"));
+
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut paused, "paused (space)");
+                if ui.add_enabled(paused, egui::Button::new("step (n)")).clicked() {
+                    step_once = true;
+                }
+                if ui.add_enabled(paused, egui::Button::new("back (b)")).clicked() {
+                    step_back = true;
+                }
+                ui.checkbox(&mut this.keep_history, "history");
+            });
+
+            ui.horizontal(|ui| {
+                let mut fixed = this.steps_per_second.is_some();
+                ui.checkbox(&mut fixed, "fixed rate");
+                this.steps_per_second =
+                    if fixed {
+                        let mut rate = this.steps_per_second.unwrap_or(10.0);
+                        ui.add(egui::DragValue::new(&mut rate).clamp_range(0.1..=240.0).suffix(" steps/s"));
+                        Some(rate)
+                    }
+                    else { None };
+            });
+            ui.label("hold f to fast forward");
+
+            this.seed = seed;
+            this.pause_when_stable = pause_when_stable;
+            this.paused = paused;
+            this.fill_threshold = fill_threshold;
+            this.keep_cells_on_resize = keep_cells_on_resize;
+            this.ascii_pattern = ascii_pattern;
+            this.ascii_error = ascii_error;
             this.rule = Some(rule);
         }
 
@@ -152,37 +677,234 @@ pub fn update(
 
         ui.label("Rules:"); {
             egui::ComboBox::from_label("color method")
-                .selected_text(format!("{:?}", this.color_method))
+                .selected_text(this.color_method.name())
                 .show_ui(ui, |ui| {
                     ui.selectable_value(&mut this.color_method, ColorMethod::Single, "Single");
                     ui.selectable_value(&mut this.color_method, ColorMethod::StateLerp, "State Lerp");
                     ui.selectable_value(&mut this.color_method, ColorMethod::DistToCenter, "Distance to Center");
                     ui.selectable_value(&mut this.color_method, ColorMethod::Neighbour, "Neighbors");
+                    ui.selectable_value(&mut this.color_method, ColorMethod::NeighbourDensity, "Neighbor Density");
+                    ui.selectable_value(&mut this.color_method, ColorMethod::PositionRgb, "Position RGB");
+                    ui.selectable_value(&mut this.color_method, ColorMethod::HotCore, "Hot Core");
+                    ui.selectable_value(&mut this.color_method, ColorMethod::GradientFlow, "Gradient Flow");
+
+                    let is_gradient = matches!(this.color_method, ColorMethod::StateGradient { .. });
+                    if ui.selectable_label(is_gradient, "State Gradient").clicked() && !is_gradient {
+                        this.color_method = ColorMethod::StateGradient { start: this.color1, end: this.color2 };
+                    }
+
+                    let is_birth_time = matches!(this.color_method, ColorMethod::BirthTime { .. });
+                    if ui.selectable_label(is_birth_time, "Birth Time").clicked() && !is_birth_time {
+                        this.color_method = ColorMethod::BirthTime { fade: 50 };
+                    }
                 });
 
             color_picker(ui, &mut this.color1);
             color_picker(ui, &mut this.color2);
 
+            if let ColorMethod::StateGradient { start, end } = &mut this.color_method {
+                ui.label("gradient:");
+                color_picker(ui, start);
+                color_picker(ui, end);
+            }
+            // only leddoo's atomic sim tracks births.
+            if let ColorMethod::BirthTime { fade } = &mut this.color_method {
+                ui.add(egui::Slider::new(fade, 1..=500).text("fade generations"));
+            }
+
+            // the shortcuts are off while this (or any) text field has focus,
+            // see update's keys.
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut this.palette_path);
+                if ui.button("load palette").clicked() {
+                    match Palette::from_path(&this.palette_path) {
+                        Ok(palette) => {
+                            this.color_method = ColorMethod::Palette(palette);
+                            this.palette_error = None;
+                        }
+                        Err(error) => this.palette_error = Some(error.to_string()),
+                    }
+                }
+            });
+            if let Some(error) = &this.palette_error {
+                ui.label(error.as_str());
+            }
+
 
             let mut rule = this.rule.take().unwrap();
             let old_rule = rule.clone();
 
-            egui::ComboBox::from_label("Neighbor method")
-                .selected_text(format!("{:?}", rule.neighbour_method))
+            neighbour_method_ui(ui, "Neighbor method", &mut rule.neighbour_method);
+
+            // only leddoo's atomic sim counts these separately.
+            let mut separate_survival = rule.survival_neighbour_method.is_some();
+            ui.checkbox(&mut separate_survival, "separate survival neighbors");
+            if separate_survival != rule.survival_neighbour_method.is_some() {
+                rule.survival_neighbour_method = separate_survival.then(|| rule.neighbour_method);
+            }
+            if let Some(method) = &mut rule.survival_neighbour_method {
+                neighbour_method_ui(ui, "Survival neighbors", method);
+            }
+
+            egui::ComboBox::from_label("Boundary mode")
+                .selected_text(rule.boundary_mode.name())
                 .show_ui(ui, |ui| {
-                    ui.selectable_value(&mut rule.neighbour_method, NeighbourMethod::Moore, "Moore");
-                    ui.selectable_value(&mut rule.neighbour_method, NeighbourMethod::VonNeuman, "Von Neumann");
+                    ui.selectable_value(&mut rule.boundary_mode, BoundaryMode::Wrap, "Wrap");
+                    ui.selectable_value(&mut rule.boundary_mode, BoundaryMode::Clamp, "Clamp");
+                    ui.selectable_value(&mut rule.boundary_mode, BoundaryMode::Dead, "Dead");
+
+                    let is_per_axis = matches!(rule.boundary_mode, BoundaryMode::WrapAxes(_));
+                    if ui.selectable_label(is_per_axis, "Wrap per Axis").clicked() && !is_per_axis {
+                        rule.boundary_mode = BoundaryMode::WrapAxes(BVec3::new(true, true, false));
+                    }
+                });
+            if let BoundaryMode::WrapAxes(axes) = &mut rule.boundary_mode {
+                ui.horizontal(|ui| {
+                    ui.label("wrap:");
+                    ui.checkbox(&mut axes.x, "x");
+                    ui.checkbox(&mut axes.y, "y");
+                    ui.checkbox(&mut axes.z, "z");
                 });
+            }
 
             ui.add(egui::Slider::new(&mut rule.states, 1..=50)
                 .text("states"));
 
-            // TODO: survival & birth rule.
+            ui.add(egui::Slider::new(&mut rule.noise_density, 0.0..=1.0)
+                .text("noise density"));
+            egui::ComboBox::from_label("Seed shape")
+                .selected_text(rule.seed_shape.name())
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut rule.seed_shape, SeedShape::Cube, "Cube");
+                    ui.selectable_value(&mut rule.seed_shape, SeedShape::Sphere, "Sphere");
+                    ui.selectable_value(&mut rule.seed_shape, SeedShape::Shell, "Shell");
+                });
+            egui::ComboBox::from_label("Noise")
+                .selected_text(rule.noise_kind.name())
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut rule.noise_kind, NoiseKind::Uniform, "Uniform");
+                    let is_perlin = matches!(rule.noise_kind, NoiseKind::Perlin { .. });
+                    if ui.selectable_label(is_perlin, "Perlin").clicked() && !is_perlin {
+                        rule.noise_kind = NoiseKind::Perlin { frequency: 0.2, threshold: 0.0 };
+                    }
+                });
+            if let NoiseKind::Perlin { frequency, threshold } = &mut rule.noise_kind {
+                ui.add(egui::Slider::new(frequency, 0.02..=1.0).text("frequency"));
+                ui.add(egui::Slider::new(threshold, -1.0..=1.0).text("threshold"));
+            }
+
+            egui::ComboBox::from_label("Death")
+                .selected_text(rule.death_mode.name())
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut rule.death_mode, DeathMode::Decay, "Decay");
+                    ui.selectable_value(&mut rule.death_mode, DeathMode::Instant, "Instant");
+                });
+
+            // only leddoo's atomic sim enforces it.
+            egui::ComboBox::from_label("Symmetry")
+                .selected_text(rule.enforce_symmetry.map_or("None", |symmetry| symmetry.name()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut rule.enforce_symmetry, None, "None");
+                    for symmetry in [Symmetry::MirrorX, Symmetry::MirrorXY, Symmetry::Cubic, Symmetry::Octahedral] {
+                        ui.selectable_value(&mut rule.enforce_symmetry, Some(symmetry), symmetry.name());
+                    }
+                });
+
+            ui.checkbox(&mut rule.count_self, "count self (totalistic)");
+            ui.checkbox(&mut rule.scale_by_state, "shrink dying cells");
+            ui.checkbox(&mut this.fade_dying, "fade dying cells");
+
+            ui.label(format!("rulestring: {}", rule.to_rulestring()));
+            ui.label("birth: [ / ], survival: ; / '");
+            if ui.button("random rule (g)").clicked() {
+                randomize_rule = true;
+            }
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut this.rulestring);
+                if ui.button("apply").clicked() {
+                    match Rule::from_rulestring(&this.rulestring) {
+                        Ok(parsed) => {
+                            rule = Rule {
+                                boundary_mode: rule.boundary_mode,
+                                scale_by_state: rule.scale_by_state,
+                                noise_density: rule.noise_density,
+                                seed_shape: rule.seed_shape,
+                                noise_kind: rule.noise_kind,
+                                death_mode: rule.death_mode,
+                                count_self: rule.count_self,
+                                enforce_symmetry: rule.enforce_symmetry,
+                                ..parsed
+                            };
+                            this.rulestring_error = None;
+                        }
+                        Err(error) => this.rulestring_error = Some(error.to_string()),
+                    }
+                }
+            });
+            if let Some(error) = &this.rulestring_error {
+                ui.label(error.as_str());
+            }
+
+            egui::CollapsingHeader::new("rule search").show(ui, |ui| {
+                {
+                    let settings = &mut this.search_settings;
+                    ui.add(egui::Slider::new(&mut settings.cell_count, 1..=20000).text("target cells"));
+                    ui.horizontal(|ui| {
+                        let mut use_components = settings.components.is_some();
+                        ui.checkbox(&mut use_components, "target components");
+                        settings.components =
+                            if use_components {
+                                let mut components = settings.components.unwrap_or(1);
+                                ui.add(egui::DragValue::new(&mut components).clamp_range(1..=1000));
+                                Some(components)
+                            }
+                            else { None };
+                    });
+                    ui.add(egui::Slider::new(&mut settings.generations, 10..=2000).text("generations"));
+                    ui.add(egui::Slider::new(&mut settings.iterations, 10..=2000).text("iterations"));
+                }
+
+                let mut start_search = false;
+                let mut stop_search  = false;
+                if let Some(search) = &this.search {
+                    let status = search.status();
+                    ui.label(format!("iteration {}/{}{}",
+                        status.iteration, search.settings.iterations,
+                        if status.done { ", done" } else { "" }));
+                    ui.label(format!("best: {} (score {:.3}, {:?})",
+                        status.best_rule.to_rulestring(), status.best_score, status.best_outcome));
+                    ui.horizontal(|ui| {
+                        if ui.button("apply best").clicked() {
+                            rule = status.best_rule.clone();
+                            this.rulestring = rule.to_rulestring();
+                        }
+                        if ui.button(if status.done { "clear" } else { "stop" }).clicked() {
+                            stop_search = true;
+                        }
+                    });
+                }
+                else if ui.button("search from this rule").clicked() {
+                    start_search = true;
+                }
+                if start_search {
+                    this.search = Some(RuleSearch::start(&rule, this.search_settings));
+                }
+                if stop_search {
+                    // joins the thread, see RuleSearch's drop.
+                    this.search = None;
+                }
+            });
 
-            if rule != old_rule {
+            // scale_by_state is only visual, no need to restart for it.
+            let sim_rule = Rule { scale_by_state: old_rule.scale_by_state, ..rule.clone() };
+            if sim_rule != old_rule {
+                let seed = this.seed;
+                let same_start = this.same_start_on_rule_change;
                 let sim = &mut this.sims[active_sim].1;
-                sim.reset();
-                sim.spawn_noise(&rule);
+                if !(same_start && sim.reset_to_initial(&rule)) {
+                    sim.reset();
+                    sim.spawn_noise(&rule, seed);
+                }
             }
 
             this.rule = Some(rule);
@@ -190,6 +912,17 @@ pub fn update(
 
         ui.add_space(24.0);
 
+        if !this.library.entries.is_empty() {
+            let text = match this.library_index {
+                Some(index) => format!("library: {} ({}/{}), {}",
+                    this.library.entries[index].0, index + 1, this.library.entries.len(),
+                    this.library.entries[index].1.rule.neighbour_method.label()),
+                None => format!("library: {} rules", this.library.entries.len()),
+            };
+            ui.label(text);
+            ui.label("page up/down: previous/next rule");
+        }
+
         ui.label("Examples:");
         for i in 0..this.examples.len() {
             let example = &this.examples[i];
@@ -199,56 +932,631 @@ pub fn update(
         }
     });
 
-    let rule = this.rule.take().unwrap();
+    // binary rules move from the atomic sim to the bitset sim, and back once
+    // the rule isn't binary anymore.
+    if this.auto_bitset {
+        let binary = BitsetSim::supports(this.rule.as_ref().unwrap());
+        let active = &this.sims[this.active_sim].0;
+        let on_atomic = active == SimBackend::Atomic.name();
+        let on_bitset = active == SimBackend::Bitset.name();
+        if binary && on_atomic {
+            this.switch_backend(SimBackend::Bitset);
+        }
+        else if !binary && on_bitset {
+            this.switch_backend(SimBackend::Atomic);
+        }
+    }
+    // eg: a rule with a survival neighbourhood applied on the sparse sim.
+    // leddoo's atomic sim runs every rule.
+    if !this.sims[this.active_sim].1.supports(this.rule.as_ref().unwrap()) {
+        let name = this.sims[this.active_sim].0.clone();
+        this.switch_backend(SimBackend::Atomic);
+        this.status = Some(format!("{} can't run this rule, switched to {}",
+            name, SimBackend::Atomic.name()));
+    }
+    // the switches above change the active sim.
+    active_sim = this.active_sim;
+
+    if save_cells {
+        this.save_cells(CELLS_SAVE_PATH);
+    }
+    if load_cells {
+        this.load_cells(CELLS_SAVE_PATH);
+    }
+
+    let mut rule = this.rule.take().unwrap();
     let mut renderer = this.renderer.take().unwrap();
+    let seed = this.seed;
+    let pause_when_stable = this.pause_when_stable;
+    let screenshot_interval = this.screenshot_interval;
+    let mut paused = this.paused;
+    let mut update_dt = this.update_dt;
+
+    if keys.just_pressed(KeyCode::F9) {
+        this.validate_steps = !this.validate_steps;
+    }
+    let validate_steps = this.validate_steps;
+
+    if keys.just_pressed(KeyCode::Space) {
+        paused = !paused;
+    }
+    // single steps only make sense while paused.
+    let step_once = paused && (step_once || keys.just_pressed(KeyCode::N));
+    let step_back = paused && (step_back || keys.just_pressed(KeyCode::B));
+    // b turns the history on, the steps after that can be undone.
+    if step_back && !this.keep_history {
+        this.keep_history = true;
+        this.status = Some("step back history on, it starts with the next step".into());
+    }
+    let history_length = if this.keep_history { STEP_BACK_HISTORY } else { 0 };
+
+    // birth & survival don't affect the neighbor counts, so they can change
+    // between steps without a reset.
+    let max_neighbours = rule.max_neighbours();
+    let max_survival_neighbours = rule.max_survival_neighbours();
+    if keys.just_pressed(KeyCode::LBracket)   { rule.birth_rule.narrow(); }
+    if keys.just_pressed(KeyCode::RBracket)   { rule.birth_rule.widen(max_neighbours); }
+    if keys.just_pressed(KeyCode::Semicolon)  { rule.survival_rule.narrow(); }
+    if keys.just_pressed(KeyCode::Apostrophe) { rule.survival_rule.widen(max_survival_neighbours); }
+
+    if randomize_rule || keys.just_pressed(KeyCode::G) {
+        let rule_seed = rand::random::<u64>();
+        rule = Rule::random(rule_seed);
+        // Rule::random(seed) gives the same rule again.
+        this.status = Some(format!("random rule {} (seed {})", rule.to_rulestring(), rule_seed));
+        this.rulestring = rule.to_rulestring();
+        let sim = &mut this.sims[active_sim].1;
+        sim.reset();
+        sim.spawn_noise(&rule, seed);
+    }
+
+    // with a fixed rate, the frame time is accumulated and spent in whole
+    // steps. at most MAX_STEPS_PER_FRAME, and the rest is dropped: slow steps
+    // would otherwise fall behind further and further.
+    let due_steps = match this.steps_per_second {
+        Some(rate) if !paused => {
+            this.step_accumulator += time.delta_seconds() * rate;
+            let steps = (this.step_accumulator as u32).min(MAX_STEPS_PER_FRAME);
+            this.step_accumulator = (this.step_accumulator - steps as f32).min(1.0);
+            steps
+        }
+        Some(_) => {
+            this.step_accumulator = 0.0;
+            0
+        }
+        None => 1,
+    };
 
     let sim = &mut this.sims[active_sim].1;
+    sim.set_history_length(history_length);
 
-    let t0 = std::time::Instant::now();
-    sim.update(&rule, &task_pool.0);
-    let update_dt = t0.elapsed();
+    // input is still handled while paused, so noise can be spawned.
+    sim.handle_input(keys, &rule, seed);
+
+    let running = !paused && !(pause_when_stable && sim.is_stable());
+    let mut stepped = false;
+    let steps =
+        if running { due_steps }
+        else if step_once { 1 }
+        else { 0 };
+    // whatever the rate, as many steps as fit in the budget.
+    if running && keys.pressed(KeyCode::F) {
+        let t0 = std::time::Instant::now();
+        let steps = sim.step_for(&rule, &task_pool.0, FAST_FORWARD_BUDGET);
+        update_dt = t0.elapsed() / steps;
+        stepped = true;
+    }
+    else if steps > 0 {
+        let t0 = std::time::Instant::now();
+        for _ in 0..steps {
+            sim.step(&rule, &task_pool.0);
+        }
+        update_dt = t0.elapsed() / steps;
+        stepped = true;
+    }
+    else if step_back {
+        sim.step_back();
+    }
+    if (restart || keys.just_pressed(KeyCode::Home)) && !sim.reset_to_initial(&rule) {
+        this.status = Some("this sim doesn't keep its start".into());
+    }
+
+    let generation = sim.generation();
+
+    // a full recount, so only on demand.
+    let mismatches = (validate_steps && stepped).then(|| sim.validate(&rule));
+    let on_interval = matches!(screenshot_interval,
+        Some(interval) if stepped && generation % interval.max(1) == 0);
+    if on_interval || keys.just_pressed(KeyCode::F12) {
+        screenshot_events.send(ScreenshotEvent {
+            path: format!("frame_{:06}.png", generation),
+            generation,
+        });
+    }
+
+    // only warn when crossing the threshold, not every frame.
+    let fill_ratio = sim.fill_ratio();
+    let filled = fill_ratio >= this.fill_threshold;
+    if filled && !this.filled {
+        filled_events.send(GridFilledEvent { fill_ratio });
+    }
+    this.filled = filled;
+
+    if let Some(mismatches) = mismatches {
+        if let Some(mismatches) = &mismatches {
+            if !mismatches.is_empty() {
+                eprintln!("generation {}: {} neighbor count mismatches", generation, mismatches.len());
+            }
+            for mismatch in mismatches.iter().take(10) {
+                eprintln!("    {}: expected {}, got {}", mismatch.pos, mismatch.expected, mismatch.actual);
+            }
+        }
+        this.mismatches = mismatches.map(|mismatches| mismatches.len());
+    }
 
+    if let Some(z) = this.slice {
+        let mut z = z;
+        if keys.just_pressed(KeyCode::Up)   { z += 1; }
+        if keys.just_pressed(KeyCode::Down) { z -= 1; }
+        this.slice = Some(z.clamp(0, bounds.z - 1));
+    }
+    let slice = this.slice;
+    let slice_dead_color = this.slice_dead_color;
+    let hdr = this.hdr;
+
+    // the renderer still has the values from before the step, so cells that
+    // were alive get a fresh ghost, and the others fade.
+    let ghost_ttl = this.ghost_ttl;
+    if this.ghosts.len() != renderer.cell_count() || ghost_ttl == 0 {
+        this.ghosts = vec![0; renderer.cell_count()];
+    }
+    else if stepped {
+        for (ghost, value) in this.ghosts.iter_mut().zip(&renderer.values) {
+            *ghost =
+                if *value != 0 { ghost_ttl }
+                else { ghost.saturating_sub(1) };
+        }
+    }
+
+    let track_births = this.color_method.needs_age();
+    let sim = &mut this.sims[active_sim].1;
+    sim.set_track_births(track_births);
+    renderer.colors.clear();
+    renderer.ages.clear();
     sim.render(&mut renderer);
 
-    let instance_data = &mut query.iter_mut().next().unwrap().0;
-    instance_data.truncate(0);
+    // cells with alpha go to the translucent entity. the vecs are taken out of
+    // the components and put back at the end, so they keep their capacity.
+    let mut instance_data = vec![];
+    let mut translucent_data = vec![];
+    for (mut data, translucent) in query.iter_mut() {
+        let target = if translucent.is_some() { &mut translucent_data } else { &mut instance_data };
+        *target = std::mem::take(&mut data.0);
+        target.truncate(0);
+    }
+    let fade_dying = this.fade_dying;
+    let needs_gradient = this.color_method.needs_gradient();
+
+    let mut live_min = Vec3::splat(f32::MAX);
+    let mut live_max = Vec3::splat(f32::MIN);
     for index in 0..renderer.cell_count() {
         let value     = renderer.values[index];
         let neighbors = renderer.neighbors[index];
 
         if value != 0 {
             let pos = utils::index_to_pos(index, bounds);
-            instance_data.push(InstanceData {
-                position: (pos - utils::center(bounds)).as_vec3(),
-                scale: 1.0,
-                color: this.color_method.color(
+            if matches!(slice, Some(z) if pos.z != z) {
+                continue;
+            }
+            let position = (pos - utils::center(bounds)).as_vec3();
+            live_min = live_min.min(position);
+            live_max = live_max.max(position);
+            let mut color = renderer.colors.get(index).copied().flatten().unwrap_or_else(||
+                this.color_method.color(
                     this.color1, this.color2,
-                    rule.states,
+                    &rule,
                     value, neighbors,
-                    utils::dist_to_center(pos, bounds),
-                ).into(),
+                    rule.boundary_mode.dist_to_center(pos, bounds),
+                    pos.as_vec3() / (bounds - IVec3::ONE).max(IVec3::ONE).as_vec3(),
+                    if needs_gradient {
+                        rule.boundary_mode.density_gradient(pos, bounds, |index| renderer.values[index] != 0)
+                    }
+                    else { Vec3::ZERO },
+                    renderer.ages.get(index).copied().unwrap_or(0),
+                ));
+            if fade_dying && value < rule.states {
+                color.set_a(value as f32 / rule.states as f32);
+            }
+            let target = if color.a() < 1.0 { &mut translucent_data } else { &mut instance_data };
+            target.push(InstanceData {
+                position,
+                scale:
+                    if rule.scale_by_state { value as f32 / rule.states as f32 }
+                    else { 1.0 },
+                color:
+                    if hdr { color.into() }
+                    else { utils::clamp_color(color).into() },
+            });
+        }
+        else if this.ghosts[index] > 0 {
+            let pos = utils::index_to_pos(index, bounds);
+            if matches!(slice, Some(z) if pos.z != z) {
+                continue;
+            }
+            let fade = this.ghosts[index] as f32 / ghost_ttl.max(1) as f32;
+            let mut color = this.color1;
+            color.set_a(GHOST_ALPHA*fade);
+            translucent_data.push(InstanceData {
+                position: (pos - utils::center(bounds)).as_vec3(),
+                scale: GHOST_SCALE*fade,
+                color: color.into(),
+            });
+        }
+        else if let (Some(z), Some(mut color)) = (slice, slice_dead_color) {
+            let pos = utils::index_to_pos(index, bounds);
+            if pos.z != z {
+                continue;
+            }
+            color.set_a(SLICE_DEAD_ALPHA);
+            translucent_data.push(InstanceData {
+                position: (pos - utils::center(bounds)).as_vec3(),
+                scale: SLICE_DEAD_SCALE,
+                color: color.into(),
             });
         }
     }
 
+    if toggle_recording || keys.just_pressed(KeyCode::R) {
+        match this.recording.take() {
+            Some(recording) => {
+                let path = format!("recording_{:06}.apng", generation);
+                match recording.finish(&path) {
+                    Ok(frames) => println!("wrote {} frames to {}", frames, path),
+                    Err(error) => eprintln!("couldn't write {}: {}", path, error),
+                }
+            }
+            None => {
+                let spill = this.record_to_disk.then(|| RECORD_SPILL_PATH.into());
+                match RecordSession::new(bounds, this.record_fps, spill) {
+                    Ok(recording) => this.recording = Some(recording),
+                    Err(error) => eprintln!("couldn't start recording: {}", error),
+                }
+            }
+        }
+    }
+    // the first frame is the state when recording started.
+    if let Some(recording) = &mut this.recording {
+        if stepped || recording.frame_count() == 0 {
+            let all: Vec<_> = instance_data.iter().chain(&translucent_data).copied().collect();
+            if let Err(error) = recording.capture(&all) {
+                eprintln!("recording stopped: {}", error);
+                this.recording = None;
+            }
+        }
+    }
+
+    // blending needs back to front, within the one draw call too.
+    let camera_pos = camera.iter().next().map_or(Vec3::ZERO, |transform| transform.translation);
+    translucent_data.sort_by(|a, b| {
+        let a = a.position.distance_squared(camera_pos);
+        let b = b.position.distance_squared(camera_pos);
+        b.total_cmp(&a)
+    });
+
+    let any_live = live_min.cmple(live_max).all();
+    frame_target.0 =
+        if this.auto_frame && any_live { Some((live_min, live_max)) }
+        else { None };
+
+    for (mut data, translucent) in query.iter_mut() {
+        data.0 =
+            if translucent.is_some() { std::mem::take(&mut translucent_data) }
+            else { std::mem::take(&mut instance_data) };
+    }
+
     this.bounds     = bounds;
     this.active_sim = active_sim;
     this.update_dt  = update_dt;
+    this.paused     = paused;
     this.renderer   = Some(renderer);
     this.rule       = Some(rule);
 }
 
 
+// egui instead of bevy's ui text, which would need a font asset. only reads
+// the Sim trait's accessors, like the ui.
+fn hud(
+    mut this: ResMut<Sims>,
+    keys: Res<Input<KeyCode>>,
+    diagnostics: Res<Diagnostics>,
+    mut egui_context: ResMut<EguiContext>,
+) {
+    // not while typing, like the shortcuts in update.
+    if keys.just_pressed(KeyCode::F3) && !egui_context.ctx_mut().wants_keyboard_input() {
+        this.show_hud = !this.show_hud;
+    }
+
+    if this.active_sim < this.sims.len() {
+        let this = &mut *this;
+        let sim = &this.sims[this.active_sim].1;
+        let generation = sim.generation();
+        // a reset or step back starts over.
+        if generation < this.step_times_generation {
+            this.step_times.clear();
+        }
+        if generation != this.step_times_generation {
+            if this.step_times.len() == STEP_GRAPH_LENGTH {
+                this.step_times.pop_front();
+            }
+            this.step_times.push_back(sim.last_step_millis());
+            this.step_times_generation = generation;
+        }
+    }
+
+    if !this.show_hud {
+        return;
+    }
+    // taken during update.
+    let rule = match &this.rule {
+        Some(rule) => rule,
+        None => return,
+    };
+
+    let sim = &this.sims[this.active_sim].1;
+    let fps = diagnostics.get(FrameTimeDiagnosticsPlugin::FPS).and_then(|fps| fps.average());
+    egui::Area::new("hud")
+        .anchor(egui::Align2::RIGHT_TOP, [-8.0, 8.0])
+        .interactable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label(rule.to_rulestring());
+            ui.label(format!("neighbors: {}", rule.neighbour_method.label()));
+            if let Some(survival) = rule.survival_neighbour_method {
+                ui.label(format!("survival neighbors: {}", survival.label()));
+            }
+            ui.label(format!("generation: {}", sim.generation()));
+            ui.label(format!("cells: {} ({:.1}% full)", sim.cell_count(), 100.0*sim.fill_ratio()));
+            ui.label(format!("births: {}, deaths: {}", sim.last_births(), sim.last_deaths()));
+            ui.label(format!("step: {:.2} ms", sim.last_step_millis()));
+            match fps {
+                Some(fps) => ui.label(format!("fps: {:.0}", fps)),
+                None => ui.label("fps: -"),
+            };
+            step_graph(ui, &this.step_times);
+        });
+}
+
+// a line from the oldest (left) to the newest step time (right), scaled to
+// the slowest one.
+fn step_graph(ui: &mut egui::Ui, step_times: &VecDeque<f32>) {
+    let (response, painter) = ui.allocate_painter(STEP_GRAPH_SIZE.into(), egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(96));
+
+    let max = step_times.iter().copied().fold(0.0f32, f32::max);
+    if step_times.len() < 2 || max <= 0.0 {
+        return;
+    }
+    let points = step_times.iter().enumerate()
+        .map(|(i, millis)| egui::pos2(
+            rect.left() + rect.width() * i as f32 / (STEP_GRAPH_LENGTH - 1) as f32,
+            rect.bottom() - rect.height() * millis / max))
+        .collect();
+    painter.add(egui::Shape::line(points, egui::Stroke::new(1.0, egui::Color32::LIGHT_GREEN)));
+    ui.label(format!("max: {:.2} ms", max));
+}
+
+
+// the world space ray from the camera through `cursor` (in pixels, from the
+// bottom left like bevy's cursor position).
+fn cursor_ray(camera: &Camera, transform: &GlobalTransform, cursor: Vec2, window_size: Vec2) -> (Vec3, Vec3) {
+    let ndc = cursor / window_size * 2.0 - Vec2::ONE;
+    let ndc_to_world = transform.compute_matrix() * camera.projection_matrix.inverse();
+    // reverse z: 1 is the near plane, 0 is infinitely far.
+    let near = ndc_to_world.project_point3(ndc.extend(1.0));
+    let far  = ndc_to_world.project_point3(ndc.extend(0.5));
+    (near, (far - near).normalize())
+}
+
+// walks the cells along the ray (in grid space, cell `pos` is the unit cube at
+// pos..pos + 1) and returns the first one `is_live` accepts.
+fn first_live_cell<F: Fn(IVec3) -> bool>(origin: Vec3, dir: Vec3, bounds: IVec3, is_live: F) -> Option<IVec3> {
+    let inv = dir.recip();
+    let t0 = -origin * inv;
+    let t1 = (bounds.as_vec3() - origin) * inv;
+    let t_enter = t0.min(t1).max_element().max(0.0);
+    let t_exit  = t0.max(t1).min_element();
+    if t_enter > t_exit {
+        return None;
+    }
+
+    let start = origin + dir * (t_enter + 1e-4);
+    let mut cell = ivec3(start.x.floor() as i32, start.y.floor() as i32, start.z.floor() as i32)
+        .max(IVec3::ZERO).min(bounds - IVec3::ONE);
+    let step = ivec3(
+        if dir.x < 0.0 { -1 } else { 1 },
+        if dir.y < 0.0 { -1 } else { 1 },
+        if dir.z < 0.0 { -1 } else { 1 });
+    // the ray's t at the next border on each axis, and between borders.
+    let mut t_next = (cell.as_vec3() + step.max(IVec3::ZERO).as_vec3() - origin) * inv;
+    let t_delta = inv.abs();
+    while utils::is_in_bounds(cell, bounds) {
+        if is_live(cell) {
+            return Some(cell);
+        }
+        let axis =
+            if t_next.x < t_next.y && t_next.x < t_next.z { 0 }
+            else if t_next.y < t_next.z { 1 }
+            else { 2 };
+        cell[axis] += step[axis];
+        t_next[axis] += t_delta[axis];
+    }
+    None
+}
+
+// the cell on the slice `z` where the ray crosses its middle.
+fn slice_cell(origin: Vec3, dir: Vec3, z: i32, bounds: IVec3) -> Option<IVec3> {
+    if dir.z.abs() < 1e-6 {
+        return None;
+    }
+    let t = (z as f32 + 0.5 - origin.z) / dir.z;
+    if t < 0.0 {
+        return None;
+    }
+    let hit = origin + t*dir;
+    let cell = ivec3(hit.x.floor() as i32, hit.y.floor() as i32, z);
+    if utils::is_in_bounds(cell, bounds) { Some(cell) } else { None }
+}
+
+// with edit_on_click, a left click clears the nearest live cell under the
+// cursor. in slice mode, the slice is the edit plane instead: the cell there
+// is toggled, dead or alive. clicks on nothing do nothing.
+fn pick_cells(
+    mut this: ResMut<Sims>,
+    mouse: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    cameras: Query<(&Camera, &GlobalTransform), With<RotatingCamera>>,
+    mut egui_context: ResMut<EguiContext>,
+) {
+    if !this.edit_on_click || !mouse.just_pressed(MouseButton::Left)
+    || egui_context.ctx_mut().wants_pointer_input() {
+        return;
+    }
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let (cursor, (camera, transform)) = match (window.cursor_position(), cameras.iter().next()) {
+        (Some(cursor), Some(camera)) => (cursor, camera),
+        _ => return,
+    };
+
+    let this = &mut *this;
+    // taken during update.
+    let (rule, renderer) = match (&this.rule, &this.renderer) {
+        (Some(rule), Some(renderer)) => (rule, renderer),
+        _ => return,
+    };
+    let bounds = renderer.bounds;
+    let (origin, dir) = cursor_ray(camera, transform, cursor, Vec2::new(window.width(), window.height()));
+    // the cells are rendered at pos - center, see update.
+    let origin = origin + utils::center(bounds).as_vec3() + Vec3::splat(0.5);
+    let is_live = |pos: IVec3| renderer.values[utils::pos_to_index(pos, bounds)] != 0;
+
+    let target = match this.slice {
+        Some(z) => slice_cell(origin, dir, z, bounds).map(|pos| (pos, !is_live(pos))),
+        None => first_live_cell(origin, dir, bounds, is_live).map(|pos| (pos, false)),
+    };
+    if let Some((pos, alive)) = target {
+        let value = if alive { rule.states } else { 0 };
+        if !this.sims[this.active_sim].1.set_cell(rule, pos, value) {
+            this.status = Some(format!("couldn't set the cell at {}", pos));
+        }
+    }
+}
+
+
 pub struct SimsPlugin;
 impl Plugin for SimsPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app
         .insert_resource(Sims::new())
-        .add_system(update);
+        .add_plugin(FrameTimeDiagnosticsPlugin::default())
+        .add_system(update)
+        .add_system(hud)
+        .add_system(pick_cells)
+        // after update, which sends the events & fills the instances.
+        .add_system_to_stage(CoreStage::PostUpdate, save_screenshots)
+        // last, so it sees the exit event from the same frame.
+        .add_system_to_stage(CoreStage::Last, write_run_summary);
+    }
+}
+
+
+pub const RUN_SUMMARY_PATH: &str = "run_summary.json";
+pub const RECORD_SPILL_PATH: &str = "recording_frames.raw";
+// see Sims::save_cells.
+pub const CELLS_SAVE_PATH: &str = "cells.ca3d";
+
+// drawing & encoding happen on the task pool, so an interval doesn't hitch
+// the frame.
+fn save_screenshots(
+    mut events: EventReader<ScreenshotEvent>,
+    this: Res<Sims>,
+    query: Query<&InstanceMaterialData>,
+    task_pool: Res<AsyncComputeTaskPool>,
+) {
+    for event in events.iter() {
+        let instances: Vec<InstanceData> = query.iter()
+            .flat_map(|data| data.0.iter().copied())
+            .collect();
+        let bounds = this.bounds;
+        let (path, generation) = (event.path.clone(), event.generation);
+        let status = this.task_status.clone();
+        task_pool.spawn(async move {
+            let result = RecordSession::screenshot(bounds, &instances)
+                .and_then(|png| std::fs::write(&path, png).map_err(RecordError::from));
+            *status.lock().unwrap() = Some(match result {
+                Ok(()) => format!("saved generation {} to {}", generation, path),
+                Err(error) => format!("couldn't write {}: {}", path, error),
+            });
+        }).detach();
+    }
+}
+
+fn write_run_summary(mut exits: EventReader<AppExit>, this: Res<Sims>) {
+    if exits.iter().next().is_none() {
+        return;
+    }
+    let rule = match &this.rule {
+        Some(rule) => rule,
+        None => return,
+    };
+
+    let sim = &this.sims[this.active_sim].1;
+    let summary = RunSummary {
+        rulestring: rule.to_rulestring(),
+        rule,
+        seed: this.seed,
+        generation: sim.generation(),
+        cell_count: sim.cell_count(),
+    };
+    let result = std::fs::File::create(RUN_SUMMARY_PATH)
+        .and_then(|mut file| summary.write_json(&mut file));
+    if let Err(error) = result {
+        eprintln!("couldn't write {}: {}", RUN_SUMMARY_PATH, error);
     }
 }
 
 
+// combo box & radius (or weights) slider. `label` is the combo box's id too.
+fn neighbour_method_ui(ui: &mut egui::Ui, label: &str, method: &mut NeighbourMethod) {
+    egui::ComboBox::from_label(label)
+        .selected_text(method.label())
+        .show_ui(ui, |ui| {
+            let radius = method.radius();
+            ui.selectable_value(method, NeighbourMethod::Moore(radius), "Moore");
+            ui.selectable_value(method, NeighbourMethod::VonNeuman(radius), "Von Neumann");
+
+            let is_weighted = matches!(method, NeighbourMethod::WeightedMoore { .. });
+            if ui.selectable_label(is_weighted, "Weighted Moore").clicked() && !is_weighted {
+                *method = NeighbourMethod::WeightedMoore { face: 1, edge: 1, corner: 1 };
+            }
+            ui.selectable_value(method, NeighbourMethod::Planar, "Planar (2D)");
+        });
+
+    if let NeighbourMethod::WeightedMoore { face, edge, corner } = method {
+        ui.add(egui::Slider::new(face,   0..=10).text("face weight"));
+        ui.add(egui::Slider::new(edge,   0..=10).text("edge weight"));
+        ui.add(egui::Slider::new(corner, 0..=10).text("corner weight"));
+    }
+    else if !method.is_planar() {
+        let mut radius = method.radius();
+        ui.add(egui::Slider::new(&mut radius, 1..=MAX_RADIUS)
+            .text("neighbor radius"));
+        *method = method.with_radius(radius);
+    }
+}
+
 fn color_picker(ui: &mut egui::Ui, color: &mut Color) {
     let mut c = [
         (color.r() * 255.0) as u8,
@@ -0,0 +1,297 @@
+/*
+    how it works:
+        - same update logic as leddoo's single threaded impl.
+        - but only live cells and cells with live neighbors are stored, in hash
+          maps. neighbor counts are still stored persistently.
+        - memory & update time scale with the population, not the volume. so
+          this is great for a few gliders in a big grid, and terrible for a
+          grid that's filled.
+*/
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use bevy::{
+    math::{IVec3},
+    tasks::TaskPool,
+};
+
+use crate::{
+    cell_renderer::{CellRenderer},
+    cells::{self, NeighbourMismatch},
+    rule::Rule,
+    utils,
+};
+
+
+pub struct SparseSim {
+    values: HashMap<IVec3, u8>,
+    neighbors: HashMap<IVec3, u16>,
+    bounds: IVec3,
+    generation: u64,
+    // wall clock time of the last update.
+    last_step: Duration,
+}
+
+impl SparseSim {
+    pub fn new() -> Self {
+        SparseSim {
+            values: HashMap::new(),
+            neighbors: HashMap::new(),
+            bounds: IVec3::ZERO,
+            generation: 0,
+            last_step: Duration::ZERO,
+        }
+    }
+
+    // survival counts with neighbour_method, there's only one counter. no
+    // margolus block updates or symmetry pass either.
+    pub fn supports(rule: &Rule) -> bool {
+        rule.with_survival_neighbours().is_none() && rule.margolus.is_none()
+            && rule.enforce_symmetry.is_none()
+    }
+
+    pub fn set_bounds(&mut self, new_bounds: IVec3) -> IVec3 {
+        let new_bounds = utils::clamp_bounds_quiet(new_bounds);
+        if new_bounds != self.bounds {
+            self.values.clear();
+            self.neighbors.clear();
+            self.bounds = new_bounds;
+            self.generation = 0;
+        }
+        self.bounds
+    }
+
+    pub fn resize_preserving(&mut self, rule: &Rule, new_bounds: IVec3) -> IVec3 {
+        let new_bounds = utils::clamp_bounds_quiet(new_bounds);
+        self.bounds = new_bounds;
+        self.values.retain(|pos, _| utils::is_in_bounds(*pos, new_bounds));
+
+        // wrapped neighbors depend on the bounds, so recount everything.
+        self.neighbors.clear();
+        let full: Vec<IVec3> = self.values.iter()
+            .filter(|(_, value)| **value == rule.states)
+            .map(|(pos, _)| *pos)
+            .collect();
+        for pos in full {
+            self.update_neighbors(rule, pos, true);
+        }
+        self.bounds
+    }
+
+
+    fn update_neighbors(&mut self, rule: &Rule, pos: IVec3, inc: bool) {
+        for (dir, weight) in rule.neighbours() {
+            let neighbor_pos = match rule.boundary_mode.resolve(pos + dir, self.bounds) {
+                Some(pos) => pos,
+                None => continue,
+            };
+
+            if inc {
+                *self.neighbors.entry(neighbor_pos).or_insert(0) += weight;
+            }
+            else {
+                // a missing count is an underflow, see leddoo's single threaded impl.
+                match self.neighbors.get_mut(&neighbor_pos) {
+                    Some(neighbors) if *neighbors > weight => *neighbors -= weight,
+                    Some(neighbors) if *neighbors == weight => { self.neighbors.remove(&neighbor_pos); }
+                    _ => debug_assert!(false, "neighbor count underflow at {}", neighbor_pos),
+                }
+            }
+        }
+    }
+
+    pub fn update(&mut self, rule: &Rule) {
+        // only live cells and cells with neighbors can change.
+        // unless cells can be born without neighbors.
+        let candidates: Vec<IVec3> =
+            if !rule.keeps_empty() {
+                let bounds = self.bounds;
+                (0..utils::volume(bounds))
+                    .map(|index| utils::index_to_pos(index, bounds))
+                    .collect()
+            }
+            else {
+                self.values.keys()
+                    .chain(self.neighbors.keys().filter(|pos| !self.values.contains_key(*pos)))
+                    .copied()
+                    .collect()
+            };
+
+        let mut spawns = vec![];
+        let mut deaths = vec![];
+        // dead cells a table moves into a state below full.
+        let mut wakes = vec![];
+
+        // update values.
+        for pos in candidates {
+            let neighbors = *self.neighbors.get(&pos).unwrap_or(&0);
+            // spawns & deaths are entering & leaving the full state, like
+            // leddoo's single threaded impl.
+            if let Some(transitions) = &rule.transitions {
+                let value = self.values.get(&pos).copied().unwrap_or(0);
+                let next = transitions.next(value, neighbors);
+                if next == rule.states && value != rule.states {
+                    spawns.push(pos);
+                }
+                if value == rule.states && next != rule.states {
+                    deaths.push(pos);
+                }
+                match self.values.get_mut(&pos) {
+                    Some(value) => *value = next,
+                    None if next > 0 && next < rule.states => wakes.push((pos, next)),
+                    None => {}
+                }
+                continue;
+            }
+            match self.values.get_mut(&pos) {
+                None => {
+                    if rule.birth_rule.in_range(neighbors) {
+                        spawns.push(pos);
+                    }
+                }
+                Some(value) => {
+                    if *value < rule.states || !rule.survival_rule.in_range(neighbors) {
+                        if *value == rule.states {
+                            deaths.push(pos);
+                        }
+                        *value = rule.death_mode.next(*value);
+                    }
+                }
+            }
+        }
+        self.values.extend(wakes);
+        self.values.retain(|_, value| *value > 0);
+        for pos in spawns.iter() {
+            self.values.insert(*pos, rule.states);
+        }
+
+        // update neighbors.
+        for pos in spawns {
+            self.update_neighbors(rule, pos, true);
+        }
+        for pos in deaths {
+            self.update_neighbors(rule, pos, false);
+        }
+
+        self.generation += 1;
+    }
+
+    pub fn spawn_noise(&mut self, rule: &Rule, seed: Option<u64>) {
+        utils::make_some_noise_in(utils::center(self.bounds), rule.noise_radius(self.bounds), rule.seed_shape, rule.noise_kind, rule.noise_density, seed, |pos| {
+            let pos = match rule.seed_pos(pos, self.bounds) {
+                Some(pos) => pos,
+                None => return,
+            };
+            if !self.values.contains_key(&pos) {
+                self.values.insert(pos, rule.states);
+                self.update_neighbors(rule, pos, true);
+            }
+        });
+    }
+
+    pub fn validate(&self, rule: &Rule) -> Vec<NeighbourMismatch> {
+        let bounds = self.bounds;
+        cells::neighbor_mismatches(rule, bounds,
+            |index| self.values.get(&utils::index_to_pos(index, bounds)) == Some(&rule.states),
+            |index| *self.neighbors.get(&utils::index_to_pos(index, bounds)).unwrap_or(&0))
+    }
+
+    // only cells with neighbors are kept, like update does.
+    pub fn recount_neighbours(&mut self, rule: &Rule) {
+        let bounds = self.bounds;
+        let counts = cells::count_neighbors(rule, bounds,
+            |index| self.values.get(&utils::index_to_pos(index, bounds)) == Some(&rule.states));
+        self.neighbors = counts.into_iter()
+            .enumerate()
+            .filter(|(_, count)| *count != 0)
+            .map(|(index, count)| (utils::index_to_pos(index, bounds), count))
+            .collect();
+    }
+
+    pub fn load_cells(&mut self, rule: &Rule, cells: &[(IVec3, u8)]) {
+        for (pos, value) in cells {
+            if !utils::is_in_bounds(*pos, self.bounds) {
+                continue;
+            }
+            let old_value = self.values.get(pos).copied().unwrap_or(0);
+            let new_value = (*value).min(rule.states);
+
+            if old_value == rule.states && new_value != rule.states {
+                self.update_neighbors(rule, *pos, false);
+            }
+            if new_value == rule.states && old_value != rule.states {
+                self.update_neighbors(rule, *pos, true);
+            }
+            if new_value == 0 {
+                self.values.remove(pos);
+            }
+            else {
+                self.values.insert(*pos, new_value);
+            }
+        }
+    }
+}
+
+
+impl crate::cells::Sim for SparseSim {
+    fn supports(&self, rule: &Rule) -> bool {
+        Self::supports(rule)
+    }
+
+    fn update(&mut self, rule: &Rule, _task_pool: &TaskPool) {
+        let t0 = Instant::now();
+        self.update(rule);
+        self.last_step = t0.elapsed();
+    }
+
+    fn last_step_millis(&self) -> f32 {
+        self.last_step.as_secs_f32() * 1000.0
+    }
+
+    fn render(&self, renderer: &mut CellRenderer) {
+        renderer.clear();
+        for (pos, value) in self.values.iter() {
+            let neighbors = *self.neighbors.get(pos).unwrap_or(&0);
+            renderer.set_pos(*pos, *value, neighbors);
+        }
+    }
+
+    fn spawn_noise(&mut self, rule: &Rule, seed: Option<u64>) {
+        self.spawn_noise(rule, seed);
+    }
+
+    fn cell_count(&self) -> usize {
+        self.values.len()
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn bounds(&self) -> IVec3 {
+        self.bounds
+    }
+
+    fn set_bounds(&mut self, new_bounds: IVec3) -> IVec3 {
+        self.set_bounds(new_bounds)
+    }
+
+    fn resize_preserving(&mut self, rule: &Rule, new_bounds: IVec3) -> IVec3 {
+        self.resize_preserving(rule, new_bounds)
+    }
+
+    fn validate(&self, rule: &Rule) -> Option<Vec<NeighbourMismatch>> {
+        Some(self.validate(rule))
+    }
+
+    fn recount_neighbours(&mut self, rule: &Rule) -> bool {
+        self.recount_neighbours(rule);
+        true
+    }
+
+    fn load_cells(&mut self, rule: &Rule, cells: &[(IVec3, u8)]) -> bool {
+        self.load_cells(rule, cells);
+        true
+    }
+}
@@ -8,11 +8,11 @@ pub use multi_threaded::*;
 #[derive(Debug)]
 struct CellState {
     value: u8,
-    neighbours: u8,
+    neighbours: u16,
 }
 
 impl CellState {
-    pub fn new(value: u8, neighbours: u8) -> Self {
+    pub fn new(value: u8, neighbours: u16) -> Self {
         CellState {
             value,
             neighbours,
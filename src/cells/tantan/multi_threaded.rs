@@ -6,6 +6,7 @@ use bevy::{
 };
 use futures_lite::future;
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use crate::{
     cell_renderer::{CellRenderer},
@@ -18,24 +19,28 @@ use super::CellState;
 pub struct CellsMultithreaded {
     states: Arc<RwLock<HashMap<IVec3, CellState>>>,
 
-    bounding_size: i32,
+    bounding_size: IVec3,
 
     // cached data used for calculating state
-    neighbours: Arc<RwLock<HashMap<IVec3, u8>>>,
+    neighbours: Arc<RwLock<HashMap<IVec3, u16>>>,
     changes: HashMap<IVec3, StateChange>,
     change_mask: HashMap<IVec3, bool>,
 
     change_results_cache: Vec<Arc<Mutex<Vec<(IVec3, StateChange)>>>>,
-    neighbour_results_cache: Vec<Arc<Mutex<Vec<IVec3>>>>,
+    neighbour_results_cache: Vec<Arc<Mutex<Vec<(IVec3, u16)>>>>,
 
     position_thread_cache: Vec<Arc<Mutex<Vec<IVec3>>>>,
+
+    generation: u64,
+    // wall clock time of the last update.
+    last_step: Duration,
 }
 
 pub enum StateChange {
     Decay,
     Spawn {
         // metadata
-        neighbours: u8,
+        neighbours: u16,
     },
 }
 
@@ -43,13 +48,15 @@ impl CellsMultithreaded {
     pub fn new() -> Self {
         CellsMultithreaded {
             states: Arc::new(RwLock::new(HashMap::new())),
-            bounding_size: 0,
+            bounding_size: IVec3::ZERO,
             neighbours: Arc::new(RwLock::new(HashMap::new())),
             changes: HashMap::new(),
             change_mask: HashMap::new(),
             position_thread_cache: Vec::new(),
             change_results_cache: Vec::new(),
             neighbour_results_cache: Vec::new(),
+            generation: 0,
+            last_step: Duration::ZERO,
         }
     }
 
@@ -64,13 +71,13 @@ impl CellsMultithreaded {
             let mut neighbours = self.neighbours.write().unwrap();
             for neighbour_cache in self.neighbour_results_cache.iter() {
                 let mut cache = neighbour_cache.lock().unwrap();
-                for neighbour_pos in cache.drain(..) {
+                for (neighbour_pos, weight) in cache.drain(..) {
                     // reduces indentation :)
                     if !neighbours.contains_key(&neighbour_pos) {
                         neighbours.insert(neighbour_pos, 0);
                     }
                     let neighbour = neighbours.get_mut(&neighbour_pos).unwrap();
-                    *neighbour += 1;
+                    *neighbour += weight;
 
                     // udpate mask
                     match self.change_mask.get_mut(&neighbour_pos) {
@@ -115,6 +122,8 @@ impl CellsMultithreaded {
                 cached_vec.lock().unwrap().clear();
             }
         }
+
+        self.generation += 1;
     }
 
     pub fn calculate_neighbours(&mut self, rule: &Rule, task_pool: &TaskPool)
@@ -147,7 +156,8 @@ impl CellsMultithreaded {
             let state_rc_clone = self.states.clone();
             let rule_states = rule.states;
             let rule_bounding = self.bounding_size;
-            let neighbour_method = rule.neighbour_method.clone();
+            let neighbours: Vec<(IVec3, u16)> = rule.neighbours().collect();
+            let boundary_mode = rule.boundary_mode;
             let position_cache = self.position_thread_cache[position_cache_index].clone();
             let result_cache = self.neighbour_results_cache[position_cache_index].clone();
 
@@ -160,9 +170,10 @@ impl CellsMultithreaded {
                         // count as neighbour if new
                         if cell.value == rule_states {
                             // get neighbouring cells and increment
-                            for dir in neighbour_method.get_neighbour_iter() {
-                                let neighbour_pos = utils::wrap(*cell_pos + *dir, rule_bounding);
-                                result_cache.push(neighbour_pos);
+                            for &(dir, weight) in neighbours.iter() {
+                                if let Some(neighbour_pos) = boundary_mode.resolve(*cell_pos + dir, rule_bounding) {
+                                    result_cache.push((neighbour_pos, weight));
+                                }
                             }
                         }
                     }
@@ -221,7 +232,7 @@ impl CellsMultithreaded {
                     };
                     match states.get(cell_pos) {
                         Some(cell) => {
-                            if !(rule_survival_rule.in_range_incorrect(neighbours)
+                            if !(rule_survival_rule.in_range(neighbours)
                                 && cell.value == rule_start_state_value)
                             {
                                 change_results_cache.push((*cell_pos, StateChange::Decay));
@@ -229,7 +240,7 @@ impl CellsMultithreaded {
                         }
                         None => {
                             // check if should spawn
-                            if rule_birth_rule.in_range_incorrect(neighbours) {
+                            if rule_birth_rule.in_range(neighbours) {
                                 if utils::is_in_bounds(*cell_pos, rule_bounding) {
                                     change_results_cache
                                         .push((*cell_pos, StateChange::Spawn { neighbours }));
@@ -283,8 +294,21 @@ impl CellsMultithreaded {
 
 
 impl crate::cells::Sim for CellsMultithreaded {
+    // survival counts with neighbour_method, there's only one counter. no
+    // margolus block updates, symmetry pass or transition tables either.
+    fn supports(&self, rule: &Rule) -> bool {
+        rule.with_survival_neighbours().is_none() && rule.margolus.is_none()
+            && rule.enforce_symmetry.is_none() && rule.transitions.is_none()
+    }
+
     fn update(&mut self, rule: &Rule, task_pool: &TaskPool) {
+        let t0 = Instant::now();
         self.tick(&rule, &task_pool);
+        self.last_step = t0.elapsed();
+    }
+
+    fn last_step_millis(&self) -> f32 {
+        self.last_step.as_secs_f32() * 1000.0
     }
 
     fn render(&self, renderer: &mut CellRenderer) {
@@ -294,22 +318,30 @@ impl crate::cells::Sim for CellsMultithreaded {
         }
     }
 
-    fn spawn_noise(&mut self, rule: &Rule) {
+    fn spawn_noise(&mut self, rule: &Rule, seed: Option<u64>) {
         let states = &mut self.states.write().unwrap();
-        utils::make_some_noise_default(utils::center(self.bounding_size), |pos| {
-            states.insert(pos, CellState::new(rule.states, 0));
+        let bounds = self.bounding_size;
+        utils::make_some_noise_in(utils::center(bounds), rule.noise_radius(bounds), rule.seed_shape, rule.noise_kind, rule.noise_density, seed, |pos| {
+            if let Some(pos) = rule.seed_pos(pos, bounds) {
+                states.insert(pos, CellState::new(rule.states, 0));
+            }
         });
     }
 
     fn cell_count(&self) -> usize {
         self.states.read().unwrap().len()
     }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
     
-    fn bounds(&self) -> i32 {
+    fn bounds(&self) -> IVec3 {
         self.bounding_size
     }
 
-    fn set_bounds(&mut self, new_bounds: i32) -> i32 {
+    fn set_bounds(&mut self, new_bounds: IVec3) -> IVec3 {
+        let new_bounds = utils::clamp_bounds_quiet(new_bounds);
         if new_bounds != self.bounding_size {
             *self = CellsMultithreaded::new();
         }
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 use bevy::{
     math::{ivec3, IVec3},
@@ -15,21 +16,26 @@ use super::CellState;
 
 pub struct CellsSinglethreaded {
     states: HashMap<IVec3, CellState>,
-    bounding_size: i32,
+    bounding_size: IVec3,
     // cached datta used for calculating state
-    neighbours: HashMap<IVec3, u8>,
+    neighbours: HashMap<IVec3, u16>,
     changes: HashMap<IVec3, i32>,
-    spawn: Vec<(IVec3, u8)>, // neighbours
+    spawn: Vec<(IVec3, u16)>, // neighbours
+    generation: u64,
+    // wall clock time of the last update.
+    last_step: Duration,
 }
 
 impl CellsSinglethreaded {
     pub fn new() -> Self {
         CellsSinglethreaded {
             states: HashMap::new(),
-            bounding_size: 0,
+            bounding_size: IVec3::ZERO,
             neighbours: HashMap::new(),
             changes: HashMap::new(),
             spawn: Vec::new(),
+            generation: 0,
+            last_step: Duration::ZERO,
         }
     }
 
@@ -37,6 +43,7 @@ impl CellsSinglethreaded {
         self.calculate_neighbours(rule);
         self.calculate_changes(rule);
         self.apply_changes(rule);
+        self.generation += 1;
     }
 
     pub fn calculate_neighbours(&mut self, rule: &Rule) {
@@ -44,13 +51,16 @@ impl CellsSinglethreaded {
             // count as neighbour if new
             if cell.value == rule.states {
                 // get neighbouring cells and increment
-                for dir in rule.neighbour_method.get_neighbour_iter() {
-                    let neighbour_pos = utils::wrap(*cell_pos + *dir, self.bounding_size);
+                for (dir, weight) in rule.neighbours() {
+                    let neighbour_pos = match rule.boundary_mode.resolve(*cell_pos + dir, self.bounding_size) {
+                        Some(pos) => pos,
+                        None => continue,
+                    };
                     if !self.neighbours.contains_key(&neighbour_pos) {
                         self.neighbours.insert(neighbour_pos, 0);
                     }
                     let neighbour = self.neighbours.get_mut(&neighbour_pos).unwrap();
-                    *neighbour += 1;
+                    *neighbour += weight;
                 }
             }
         }
@@ -68,7 +78,7 @@ impl CellsSinglethreaded {
                     };
                     match self.states.get(&cell_pos) {
                         Some(cell) => {
-                            if !(rule.survival_rule.in_range_incorrect(neighbours)
+                            if !(rule.survival_rule.in_range(neighbours)
                                 && cell.value == rule.states)
                             {
                                 self.changes.insert(cell_pos, -1i32);
@@ -76,7 +86,7 @@ impl CellsSinglethreaded {
                         }
                         None => {
                             // check if should spawn
-                            if rule.birth_rule.in_range_incorrect(neighbours) {
+                            if rule.birth_rule.in_range(neighbours) {
                                 // cell_pos is in bounds, because we iterate over the bounds.
                                 self.spawn.push((cell_pos, neighbours));
                             }
@@ -117,8 +127,21 @@ impl CellsSinglethreaded {
 
 
 impl crate::cells::Sim for CellsSinglethreaded {
+    // survival counts with neighbour_method, there's only one counter. no
+    // margolus block updates, symmetry pass or transition tables either.
+    fn supports(&self, rule: &Rule) -> bool {
+        rule.with_survival_neighbours().is_none() && rule.margolus.is_none()
+            && rule.enforce_symmetry.is_none() && rule.transitions.is_none()
+    }
+
     fn update(&mut self, rule: &Rule, _task_pool: &TaskPool) {
+        let t0 = Instant::now();
         self.tick(rule);
+        self.last_step = t0.elapsed();
+    }
+
+    fn last_step_millis(&self) -> f32 {
+        self.last_step.as_secs_f32() * 1000.0
     }
 
     fn render(&self, renderer: &mut CellRenderer) {
@@ -128,9 +151,12 @@ impl crate::cells::Sim for CellsSinglethreaded {
         }
     }
 
-    fn spawn_noise(&mut self, rule: &Rule) {
-        utils::make_some_noise_default(utils::center(self.bounding_size), |pos| {
-            self.states.insert(pos, CellState::new(rule.states, 0));
+    fn spawn_noise(&mut self, rule: &Rule, seed: Option<u64>) {
+        let bounds = self.bounding_size;
+        utils::make_some_noise_in(utils::center(bounds), rule.noise_radius(bounds), rule.seed_shape, rule.noise_kind, rule.noise_density, seed, |pos| {
+            if let Some(pos) = rule.seed_pos(pos, bounds) {
+                self.states.insert(pos, CellState::new(rule.states, 0));
+            }
         });
     }
 
@@ -138,11 +164,16 @@ impl crate::cells::Sim for CellsSinglethreaded {
         self.states.len()
     }
 
-    fn bounds(&self) -> i32 {
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn bounds(&self) -> IVec3 {
         self.bounding_size
     }
 
-    fn set_bounds(&mut self, new_bounds: i32) -> i32 {
+    fn set_bounds(&mut self, new_bounds: IVec3) -> IVec3 {
+        let new_bounds = utils::clamp_bounds_quiet(new_bounds);
         if new_bounds != self.bounding_size {
             *self = CellsSinglethreaded::new();
         }
@@ -0,0 +1,20 @@
+// the sims, rules & rendering. main.rs builds the app out of these, the
+// benches in benches/ step the sims without it.
+
+// the sims are made with new() everywhere, most of them can't have a
+// meaningful Default.
+#![allow(clippy::new_without_default)]
+
+pub mod cell_event;
+pub mod cell_renderer;
+pub mod neighbours;
+pub mod palette;
+pub mod patterns;
+pub mod recording;
+pub mod rotating_camera;
+pub mod rule;
+pub mod search;
+pub mod stats;
+pub mod utils;
+
+pub mod cells;
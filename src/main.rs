@@ -1,21 +1,134 @@
 use bevy::{prelude::*, render::view::NoFrustumCulling};
 use bevy_egui::{EguiPlugin};
-use cell_event::CellStatesChangedEvent;
-pub mod cell_event;
-mod cell_renderer;
-mod neighbours;
-mod rotating_camera;
-mod rule;
-mod utils;
+use celluar_automata::{cell_event, cell_renderer, cells, neighbours, rotating_camera, rule};
+use cell_event::{CellStatesChangedEvent, GridFilledEvent, ScreenshotEvent};
 use cell_renderer::*;
 use neighbours::NeighbourMethod;
 use rotating_camera::{RotatingCamera, RotatingCameraPlugin};
 use rule::*;
 
-mod cells;
-use cells::sims::Example;
+use cells::{sims::Example, SimBackend};
+
+fn all_sims() -> Vec<(String, Box<dyn cells::Sim>)> {
+    fn sim<S: cells::Sim + 'static>(name: &str, sim: S) -> (String, Box<dyn cells::Sim>) {
+        (name.into(), Box::new(sim))
+    }
+
+    vec![
+        sim("tantan single-threaded", cells::tantan::CellsSinglethreaded::new()),
+        sim("tantan multi-threaded",  cells::tantan::CellsMultithreaded::new()),
+    ]
+    .into_iter()
+    .chain(SimBackend::ALL.into_iter().map(|backend| (backend.name().into(), backend.build())))
+    .collect()
+}
+
+// left half 445, right half builder.
+fn composite_demo() -> cells::composite::CompositeSim {
+    use cells::composite::{BoundingRegion, CompositeSim};
+
+    let left  = BoundingRegion::new(Vec3::ZERO, Vec3::new(0.5, 1.0, 1.0));
+    let right = BoundingRegion::new(Vec3::new(0.5, 0.0, 0.0), Vec3::ONE);
+    let mut sim = CompositeSim::new(vec![
+        (left,  Rule::from_rulestring("B4/S4/5/M").unwrap()),
+        (right, Rule::from_rulestring("B4,6,8-10/S2,6,9/10/M").unwrap()),
+    ]);
+    sim.set_region_color(0, ColorMethod::StateLerp, Color::BLACK, Color::RED);
+    sim.set_region_color(1, ColorMethod::DistToCenter, Color::YELLOW, Color::BLUE);
+    sim
+}
+
+// the rule library, flipped through with page up/down.
+const RULES_DIR: &str = "rules";
+
+// `cargo run --release -- --bench`: steps all sims without opening a window.
+fn bench() {
+    let rule = Rule::from_rulestring("B4/S4/5/M").unwrap();
+    let task_pool = bevy::tasks::TaskPool::new();
+    // tantan's sims were never checked cell for cell against the others, so
+    // they are only compared with each other.
+    let (mut tantan, mut others): (Vec<_>, Vec<_>) = all_sims().into_iter()
+        .partition(|(name, _)| name.starts_with("tantan"));
+    // other chunk sizes, the app only uses the default.
+    others.push(("leddoo atomic 8".into(),
+        Box::new(cells::leddoo::LeddooAtomicChunked::<8>::new())));
+    others.push(("leddoo atomic 16".into(),
+        Box::new(cells::leddoo::LeddooAtomicChunked::<16>::new())));
+
+    let mut results = cells::benchmark(
+        &mut tantan, &rule, IVec3::splat(64), 42, 100, &task_pool);
+    results.extend(cells::benchmark(
+        &mut others, &rule, IVec3::splat(64), 42, 100, &task_pool));
+    for result in results {
+        println!("{:>24}: {:8.2} steps/s, {} cells",
+            result.name, result.steps_per_second, result.cell_count);
+    }
+
+    // a solid start, so most of the first steps are neighbor updates. this is
+    // the case the `moore_fast_path` feature is for. benches/backends.rs
+    // times both paths in one build.
+    let dense = Rule { noise_density: 1.0, ..rule };
+    let mut atomic: Vec<(String, Box<dyn cells::Sim>)> = others.into_iter()
+        .filter(|(name, _)| name.starts_with("leddoo atomic"))
+        .collect();
+    let results = cells::benchmark(
+        &mut atomic, &dense, IVec3::splat(64), 42, 100, &task_pool);
+    println!("dense M1 (moore_fast_path: {}):", cfg!(feature = "moore_fast_path"));
+    for result in results {
+        println!("{:>24}: {:8.2} steps/s, {} cells",
+            result.name, result.steps_per_second, result.cell_count);
+    }
+
+    // the bitset sim only runs binary rules. that it matches the atomic sim
+    // is checked in its tests.
+    let binary = Rule::from_rulestring("B4/S4/1/M").unwrap();
+    let mut binary_sims: Vec<(String, Box<dyn cells::Sim>)> = vec![
+        (SimBackend::Atomic.name().into(), SimBackend::Atomic.build()),
+        (SimBackend::Bitset.name().into(), SimBackend::Bitset.build()),
+    ];
+    let results = cells::benchmark(
+        &mut binary_sims, &binary, IVec3::splat(64), 42, 100, &task_pool);
+    println!("binary {}:", binary.to_rulestring());
+    for result in results {
+        println!("{:>24}: {:8.2} steps/s, {} cells",
+            result.name, result.steps_per_second, result.cell_count);
+    }
+}
+
+// `cargo run --release -- --classify < rules.txt`: one rulestring per line,
+// prints how each run from the same noise ended.
+fn classify() {
+    use std::io::BufRead;
+
+    let task_pool = bevy::tasks::TaskPool::new();
+    let mut sim = cells::leddoo::LeddooAtomic::new();
+    for line in std::io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(error) => { eprintln!("{}", error); return; }
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let rule = match Rule::from_rulestring(line) {
+            Ok(rule) => rule,
+            Err(error) => { eprintln!("{}: {}", line, error); continue; }
+        };
+
+        sim.set_bounds(IVec3::splat(32));
+        sim.spawn_noise(&rule, Some(42));
+        println!("{}: {:?}", line, sim.run_until(&rule, &task_pool, 1000));
+    }
+}
 
 fn main() {
+    match std::env::args().nth(1).as_deref() {
+        Some("--bench") => { bench(); return; }
+        Some("--classify") => { classify(); return; }
+        _ => (),
+    }
+
     let mut task_pool_settings = DefaultTaskPoolOptions::default();
     task_pool_settings.async_compute.percent = 1.0f32;
     task_pool_settings.compute.percent = 0.0f32; // i currently only use async_compute
@@ -27,6 +140,8 @@ fn main() {
         .add_plugin(EguiPlugin)
         .insert_resource(ClearColor(Color::rgb(0.65f32, 0.9f32, 0.96f32)))
         .add_event::<CellStatesChangedEvent>()
+        .add_event::<GridFilledEvent>()
+        .add_event::<ScreenshotEvent>()
         .add_plugin(RotatingCameraPlugin)
         .add_plugin(CellMaterialPlugin)
         .add_plugin(cells::SimsPlugin)
@@ -39,17 +154,13 @@ fn setup(
     mut meshes: ResMut<Assets<Mesh>>,
     mut sims: ResMut<cells::Sims>,
 ) {
-    sims.add_sim("tantan single-threaded".into(),
-        Box::new(cells::tantan::CellsSinglethreaded::new()));
+    for (name, sim) in all_sims() {
+        sims.add_sim(name, sim);
+    }
 
-    sims.add_sim("tantan multi-threaded".into(),
-        Box::new(cells::tantan::CellsMultithreaded::new()));
-
-    sims.add_sim("leddoo single-threaded".into(),
-        Box::new(cells::leddoo::LeddooSingleThreaded::new()));
-
-    sims.add_sim("leddoo atomic".into(),
-        Box::new(cells::leddoo::LeddooAtomic::new()));
+    // not in all_sims, it ignores the ui's rule, so benchmarking it against
+    // the other sims makes no sense.
+    sims.add_sim("composite (445 | builder)".into(), Box::new(composite_demo()));
 
 
     sims.add_example(Example {
@@ -58,7 +169,8 @@ fn setup(
             survival_rule: Value::new(&[2, 6, 9]),
             birth_rule: Value::new(&[4, 6, 8, 9, 10]),
             states: 10,
-            neighbour_method: NeighbourMethod::Moore,
+            neighbour_method: NeighbourMethod::Moore(1),
+            ..Default::default()
         },
         color_method: ColorMethod::DistToCenter,
         color1: Color::YELLOW,
@@ -71,7 +183,8 @@ fn setup(
             survival_rule: Value::from_range(0..=6),
             birth_rule: Value::new(&[1,3]),
             states: 2,
-            neighbour_method: NeighbourMethod::VonNeuman,
+            neighbour_method: NeighbourMethod::VonNeuman(1),
+            ..Default::default()
         },
         color_method: ColorMethod::DistToCenter,
         color1: Color::GREEN,
@@ -84,7 +197,8 @@ fn setup(
             survival_rule: Value::new(&[0,1,2,3,7,8,9,11,13,18,21,22,24,26]),
             birth_rule: Value::new(&[4,13,17,20,21,22,23,24,26]),
             states: 4,
-            neighbour_method: NeighbourMethod::Moore,
+            neighbour_method: NeighbourMethod::Moore(1),
+            ..Default::default()
         },
         color_method: ColorMethod::StateLerp,
         color1: Color::RED,
@@ -97,7 +211,8 @@ fn setup(
             survival_rule: Value::new(&[5,6,7,8]),
             birth_rule: Value::new(&[6,7,9]),
             states: 10,
-            neighbour_method: NeighbourMethod::Moore,
+            neighbour_method: NeighbourMethod::Moore(1),
+            ..Default::default()
         },
         color_method: ColorMethod::DistToCenter,
         color1: Color::GREEN,
@@ -110,7 +225,8 @@ fn setup(
             survival_rule: Value::new(&[3,6,9]),
             birth_rule: Value::new(&[4,8,10]),
             states: 20,
-            neighbour_method: NeighbourMethod::Moore,
+            neighbour_method: NeighbourMethod::Moore(1),
+            ..Default::default()
         },
         color_method: ColorMethod::StateLerp,
         color1: Color::RED,
@@ -123,7 +239,8 @@ fn setup(
             survival_rule: Value::from_range(9..=26),
             birth_rule: Value::new(&[5,6,7,12,13,15]),
             states: 20,
-            neighbour_method: NeighbourMethod::Moore,
+            neighbour_method: NeighbourMethod::Moore(1),
+            ..Default::default()
         },
         color_method: ColorMethod::StateLerp,
         color1: Color::YELLOW,
@@ -136,7 +253,8 @@ fn setup(
             survival_rule: Value::new(&[4]),
             birth_rule: Value::new(&[4]),
             states: 5,
-            neighbour_method: NeighbourMethod::Moore,
+            neighbour_method: NeighbourMethod::Moore(1),
+            ..Default::default()
         },
         color_method: ColorMethod::StateLerp,
         color1: Color::BLACK,
@@ -149,7 +267,8 @@ fn setup(
             survival_rule: Value::new(&[4]),
             birth_rule: Value::new(&[3]),
             states: 20,
-            neighbour_method: NeighbourMethod::Moore,
+            neighbour_method: NeighbourMethod::Moore(1),
+            ..Default::default()
         },
         color_method: ColorMethod::StateLerp,
         color1: Color::BLACK,
@@ -162,7 +281,8 @@ fn setup(
             survival_rule: Value::new(&[6,7]),
             birth_rule: Value::new(&[4,6,9,10,11]),
             states: 6,
-            neighbour_method: NeighbourMethod::Moore,
+            neighbour_method: NeighbourMethod::Moore(1),
+            ..Default::default()
         },
         color_method: ColorMethod::StateLerp,
         color1: Color::BLUE,
@@ -175,7 +295,8 @@ fn setup(
             survival_rule: Value::new(&[5]),
             birth_rule: Value::new(&[4, 6, 9, 10, 11, 16, 17, 18, 19, 20, 21, 22, 23, 24]),
             states: 35,
-            neighbour_method: NeighbourMethod::Moore,
+            neighbour_method: NeighbourMethod::Moore(1),
+            ..Default::default()
         },
         color_method: ColorMethod::StateLerp,
         color1: Color::BLUE,
@@ -183,7 +304,88 @@ fn setup(
     });
 
 
-    sims.set_example(0);
+    sims.add_example(Example {
+        name: "brian's brain".into(),
+        rule: Rule {
+            survival_rule: Value::new(&[]),
+            birth_rule: Value::new(&[]),
+            states: 2,
+            neighbour_method: NeighbourMethod::Moore(1),
+            // firing (2) -> refractory (1) -> off (0) -> firing if 2 neighbors fire.
+            transitions: Some(TransitionTable::from_fn(2, |value, neighbours| {
+                match value {
+                    0 if neighbours == 2 => 2,
+                    0 => 0,
+                    value => value - 1,
+                }
+            })),
+            ..Default::default()
+        },
+        color_method: ColorMethod::StateLerp,
+        color1: Color::BLUE,
+        color2: Color::WHITE,
+    });
+
+    // reversible, so step back (b) works for as long as you like. only on
+    // leddoo's atomic sim, the others ignore the margolus table.
+    sims.add_example(Example {
+        name: "margolus gas".into(),
+        rule: Rule {
+            survival_rule: Value::new(&[]),
+            birth_rule: Value::new(&[]),
+            states: 1,
+            neighbour_method: NeighbourMethod::Moore(1),
+            noise_density: 0.1,
+            margolus: Some(MargolusTable::gas()),
+            ..Default::default()
+        },
+        color_method: ColorMethod::DistToCenter,
+        color1: Color::WHITE,
+        color2: Color::BLUE,
+    });
+
+    // 2d conway on the center z plane, for checking known 2d patterns.
+    sims.add_example(Example {
+        name: "conway 2d".into(),
+        rule: Rule {
+            survival_rule: Value::new(&[2, 3]),
+            birth_rule: Value::new(&[3]),
+            states: 1,
+            neighbour_method: NeighbourMethod::Planar,
+            ..Default::default()
+        },
+        color_method: ColorMethod::Single,
+        color1: Color::BLACK,
+        color2: Color::WHITE,
+    });
+
+
+    // a rule file (see RuleFile) can be passed as the first argument.
+    let mut first_example = 0;
+    if let Some(path) = std::env::args().nth(1) {
+        match RuleFile::from_toml_path(&path) {
+            Ok(file) => {
+                first_example = sims.add_example(Example {
+                    name: path,
+                    rule: file.rule,
+                    color_method: file.color_method.unwrap_or(ColorMethod::DistToCenter),
+                    color1: Color::YELLOW,
+                    color2: Color::RED,
+                });
+                if let Some(size) = file.bounding_size {
+                    sims.set_bounds(IVec3::splat(size));
+                }
+            }
+            Err(error) => eprintln!("couldn't load rule file {:?}: {}", path, error),
+        }
+    }
+
+    sims.set_example(first_example);
+
+    match RuleLibrary::from_dir(RULES_DIR) {
+        Ok(library) => sims.set_library(library),
+        Err(error) => eprintln!("couldn't read {:?}: {}", RULES_DIR, error),
+    }
 
 
     commands.spawn().insert_bundle((
@@ -212,6 +414,18 @@ fn setup(
         NoFrustumCulling,
     ));
 
+    // the alpha blended cells (fading & ghosts), see Translucent.
+    commands.spawn().insert_bundle((
+        meshes.add(Mesh::from(shape::Cube { size: 1.0 })),
+        Transform::from_xyz(0.0, 0.0, 0.0),
+        GlobalTransform::default(),
+        InstanceMaterialData(vec![]),
+        Translucent,
+        Visibility::default(),
+        ComputedVisibility::default(),
+        NoFrustumCulling,
+    ));
+
     // camera
     commands
         .spawn_bundle(PerspectiveCameraBundle {
@@ -1,19 +1,129 @@
-use bevy::math::{const_ivec3, IVec3};
+use bevy::math::{const_ivec3, ivec3, IVec3};
+use std::sync::OnceLock;
 
+pub const MAX_RADIUS: u8 = 3;
+// moore neighbourhood with MAX_RADIUS: (2*3 + 1)^3 - 1.
+pub const MAX_NEIGHBOURS: usize = 342;
+
+// the u8 is the radius (1..=MAX_RADIUS).
 #[allow(dead_code)]
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum NeighbourMethod {
-    Moore,
-    VonNeuman,
+    Moore(u8),
+    VonNeuman(u8),
+    // radius 1 moore, but face/edge/corner neighbours count `face`/`edge`/
+    // `corner` times. the weighted sum is what birth & survival check.
+    WeightedMoore { face: u8, edge: u8, corner: u8 },
+    // the 8 radius 1 moore neighbours in the same z plane. for 2d rules like
+    // conway's, which then run independently on every plane.
+    Planar,
 }
 
 impl NeighbourMethod {
+    pub fn radius(&self) -> u8 {
+        match self {
+            NeighbourMethod::Moore(radius) | NeighbourMethod::VonNeuman(radius) =>
+                (*radius).clamp(1, MAX_RADIUS),
+            NeighbourMethod::WeightedMoore { .. } | NeighbourMethod::Planar => 1,
+        }
+    }
+
+    // for the ui, see label.
+    pub fn name(&self) -> &'static str {
+        match self {
+            NeighbourMethod::Moore(_) => "Moore",
+            NeighbourMethod::VonNeuman(_) => "von Neumann",
+            NeighbourMethod::WeightedMoore { .. } => "Weighted Moore",
+            NeighbourMethod::Planar => "Planar",
+        }
+    }
+
+    // the neighbours in use, without weights. eg: for printing.
+    pub fn offsets(&self) -> &'static [IVec3] {
+        self.get_neighbour_iter()
+    }
+
+    // "Moore (26)", the count tells the radius apart.
+    pub fn label(&self) -> String {
+        format!("{} ({})", self.name(), self.offsets().len())
+    }
+
+    pub fn is_planar(&self) -> bool {
+        matches!(self, NeighbourMethod::Planar)
+    }
+
+    pub fn with_radius(&self, radius: u8) -> Self {
+        match self {
+            NeighbourMethod::Moore(_) => NeighbourMethod::Moore(radius),
+            NeighbourMethod::VonNeuman(_) => NeighbourMethod::VonNeuman(radius),
+            NeighbourMethod::WeightedMoore { .. } | NeighbourMethod::Planar => *self,
+        }
+    }
+
+    // how much the neighbour at `dir` adds to the count.
+    pub fn weight(&self, dir: IVec3) -> u16 {
+        match self {
+            NeighbourMethod::WeightedMoore { face, edge, corner } => {
+                match dir.abs().dot(IVec3::ONE) {
+                    1 => *face as u16,
+                    2 => *edge as u16,
+                    _ => *corner as u16,
+                }
+            }
+            _ => 1,
+        }
+    }
+
+    pub fn weighted_neighbours(&self) -> impl Iterator<Item = (IVec3, u16)> + '_ {
+        self.get_neighbour_iter().iter().map(move |dir| (*dir, self.weight(*dir)))
+    }
+
+    // u16, as radius 3 moore has 342 neighbours.
+    // weighted sums above MAX_NEIGHBOURS never match birth/survival.
+    pub fn max_neighbours(&self) -> u16 {
+        self.weighted_neighbours().map(|(_, weight)| weight).sum()
+    }
+
     pub fn get_neighbour_iter(&self) -> &'static [IVec3] {
+        // larger neighbourhoods are generated on first use.
+        static MOORE: OnceLock<Vec<Vec<IVec3>>> = OnceLock::new();
+        static VONNEUMAN: OnceLock<Vec<Vec<IVec3>>> = OnceLock::new();
+
+        let radius = self.radius();
         match self {
-            NeighbourMethod::Moore => &MOOSE_NEIGHBOURS[..],
-            NeighbourMethod::VonNeuman => &VONNEUMAN_NEIGHBOURS[..],
+            NeighbourMethod::Moore(_) if radius == 1 => &MOOSE_NEIGHBOURS[..],
+            NeighbourMethod::VonNeuman(_) if radius == 1 => &VONNEUMAN_NEIGHBOURS[..],
+            NeighbourMethod::WeightedMoore { .. } => &MOOSE_NEIGHBOURS[..],
+            NeighbourMethod::Planar => &PLANAR_NEIGHBOURS[..],
+            NeighbourMethod::Moore(_) => MOORE
+                .get_or_init(|| (1..=MAX_RADIUS).map(|radius| ball(radius, |pos| {
+                    pos.x.abs().max(pos.y.abs()).max(pos.z.abs())
+                })).collect())[radius as usize - 1]
+                .as_slice(),
+            NeighbourMethod::VonNeuman(_) => VONNEUMAN
+                .get_or_init(|| (1..=MAX_RADIUS).map(|radius| ball(radius, |pos| {
+                    pos.x.abs() + pos.y.abs() + pos.z.abs()
+                })).collect())[radius as usize - 1]
+                .as_slice(),
+        }
+    }
+}
+
+// all offsets (except the center) whose distance to the center is <= radius.
+fn ball(radius: u8, distance: fn(IVec3) -> i32) -> Vec<IVec3> {
+    let radius = radius as i32;
+    let mut result = vec![];
+    for z in -radius..=radius {
+        for y in -radius..=radius {
+            for x in -radius..=radius {
+                let pos = ivec3(x, y, z);
+                if pos != IVec3::ZERO && distance(pos) <= radius {
+                    result.push(pos);
+                }
+            }
         }
     }
+    result
 }
 
 pub static VONNEUMAN_NEIGHBOURS: [IVec3; 6] = [
@@ -25,6 +135,17 @@ pub static VONNEUMAN_NEIGHBOURS: [IVec3; 6] = [
     const_ivec3!([0, 0, 1]),
 ];
 
+pub static PLANAR_NEIGHBOURS: [IVec3; 8] = [
+    const_ivec3!([-1, -1, 0]),
+    const_ivec3!([0, -1, 0]),
+    const_ivec3!([1, -1, 0]),
+    const_ivec3!([-1, 0, 0]),
+    const_ivec3!([1, 0, 0]),
+    const_ivec3!([-1, 1, 0]),
+    const_ivec3!([0, 1, 0]),
+    const_ivec3!([1, 1, 0]),
+];
+
 pub static MOOSE_NEIGHBOURS: [IVec3; 26] = [
     const_ivec3!([-1, -1, -1]),
     const_ivec3!([0, -1, -1]),
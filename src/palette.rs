@@ -0,0 +1,110 @@
+/*
+    palette files, one color per cell value: the first color is value 1 (about
+    to die), the last one the full state of a rule with as many states.
+        - gimp palettes (.gpl): a "GIMP Palette" header, optional "Name:" &
+          "Columns:" lines, "#" comments, then "r g b name" per color, 0..255.
+        - anything else is a hex list: one "#rrggbb" or "rrggbb" per line,
+          empty lines & lines starting with "//" are skipped.
+*/
+
+use bevy::prelude::Color;
+use serde::{Deserialize, Serialize};
+
+use std::io;
+use std::path::Path;
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Palette(pub Vec<Color>);
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(io::Error),
+    // 1 based line number & the line.
+    InvalidLine(usize, String),
+    Empty,
+}
+
+impl std::fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PaletteError::Io(error) =>
+                write!(f, "io error: {}", error),
+            PaletteError::InvalidLine(line, text) =>
+                write!(f, "line {} is not a color: {:?}", line, text),
+            PaletteError::Empty =>
+                write!(f, "the palette has no colors"),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+impl From<io::Error> for PaletteError {
+    fn from(error: io::Error) -> Self {
+        PaletteError::Io(error)
+    }
+}
+
+
+impl Palette {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Palette, PaletteError> {
+        let text = std::fs::read_to_string(path)?;
+        Palette::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Palette, PaletteError> {
+        let is_gpl = text.lines().next().map_or(false, |line| line.trim() == "GIMP Palette");
+
+        let mut colors = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let color =
+                if is_gpl {
+                    if number == 0 || line.is_empty() || line.starts_with('#')
+                    || line.starts_with("Name:") || line.starts_with("Columns:") {
+                        continue;
+                    }
+                    parse_gpl_color(line)
+                }
+                else {
+                    if line.is_empty() || line.starts_with("//") {
+                        continue;
+                    }
+                    parse_hex_color(line)
+                };
+            match color {
+                Some(color) => colors.push(color),
+                None => return Err(PaletteError::InvalidLine(number + 1, line.into())),
+            }
+        }
+
+        if colors.is_empty() {
+            return Err(PaletteError::Empty);
+        }
+        Ok(Palette(colors))
+    }
+
+    // values past the end get the last color.
+    pub fn color(&self, state: u8) -> Color {
+        let index = (state as usize).saturating_sub(1);
+        self.0[index.min(self.0.len() - 1)]
+    }
+}
+
+// "r g b" and an optional name.
+fn parse_gpl_color(line: &str) -> Option<Color> {
+    let mut parts = line.split_whitespace();
+    let mut channel = || parts.next()?.parse::<u8>().ok();
+    let (r, g, b) = (channel()?, channel()?, channel()?);
+    Some(Color::rgb_u8(r, g, b))
+}
+
+fn parse_hex_color(line: &str) -> Option<Color> {
+    let hex = line.strip_prefix('#').unwrap_or(line);
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some(Color::rgb_u8((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
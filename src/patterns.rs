@@ -0,0 +1,450 @@
+/*
+    3d rle patterns, in golly's .rle3 format:
+        - `#` lines are comments.
+        - header: `x = 3, y = 3, z = 2, rule = ...` (z defaults to 1, the rule
+          is ignored, it's not in our rulestring format anyway).
+        - body: runs of `<count><token>`, count defaults to 1.
+            - `b` or `.`: dead.
+            - `o`: alive, loaded as u8::MAX, which stamping clamps to the
+              rule's full state.
+            - `A`..`X`: states 1..=24, `pA`..`yO`: states 25..=255.
+            - `$`: next row, `/`: next plane, `!`: end.
+        - values are this sim's cell values (full state = alive, counting
+          down to 1 while dying), not golly's generations states.
+*/
+
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use bevy::math::{ivec3, IVec3};
+
+
+#[derive(Debug)]
+pub enum RleError {
+    Io(io::Error),
+    // no `x = .., y = ..` line before the body.
+    MissingHeader,
+    InvalidHeader(String),
+    InvalidToken(char),
+    StateOutOfRange(u32),
+    CellOutOfBounds(IVec3),
+}
+
+impl std::fmt::Display for RleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RleError::Io(error) =>
+                write!(f, "io error: {}", error),
+            RleError::MissingHeader =>
+                write!(f, "expected a header like x = <x>, y = <y>, z = <z>"),
+            RleError::InvalidHeader(text) =>
+                write!(f, "invalid header: {:?}", text),
+            RleError::InvalidToken(c) =>
+                write!(f, "invalid token: {:?}", c),
+            RleError::StateOutOfRange(state) =>
+                write!(f, "state {} is out of range", state),
+            RleError::CellOutOfBounds(pos) =>
+                write!(f, "cell at {} is outside the header's size", pos),
+        }
+    }
+}
+
+impl std::error::Error for RleError {}
+
+impl From<io::Error> for RleError {
+    fn from(error: io::Error) -> Self {
+        RleError::Io(error)
+    }
+}
+
+
+pub const RLE_ALIVE: u8 = u8::MAX;
+
+fn parse_header(line: &str) -> Result<IVec3, RleError> {
+    let invalid = || RleError::InvalidHeader(line.into());
+
+    let mut size = ivec3(0, 0, 1);
+    for item in line.split(',') {
+        let (key, value) = item.split_once('=').ok_or_else(invalid)?;
+        let axis = match key.trim() {
+            "x" => &mut size.x,
+            "y" => &mut size.y,
+            "z" => &mut size.z,
+            _ => continue,
+        };
+        *axis = value.trim().parse().map_err(|_| invalid())?;
+    }
+
+    if size.x <= 0 || size.y <= 0 || size.z <= 0 {
+        return Err(invalid());
+    }
+    Ok(size)
+}
+
+// returns the live cells, relative to the pattern's corner.
+#[allow(dead_code)]
+pub fn load_rle3<R: Read>(r: &mut R) -> Result<Vec<(IVec3, u8)>, RleError> {
+    let mut text = String::new();
+    r.read_to_string(&mut text)?;
+
+    let mut lines = text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+    let header = lines.next().ok_or(RleError::MissingHeader)?;
+    if !header.starts_with('x') {
+        return Err(RleError::MissingHeader);
+    }
+    let size = parse_header(header)?;
+
+    let mut cells = vec![];
+    let mut pos = IVec3::ZERO;
+    let mut count: Option<u32> = None;
+    let mut prefix: Option<u32> = None;
+
+    'body: for line in lines {
+        for c in line.chars() {
+            if let Some(digit) = c.to_digit(10) {
+                if prefix.is_some() {
+                    return Err(RleError::InvalidToken(c));
+                }
+                count = Some(count.unwrap_or(0)*10 + digit);
+                continue;
+            }
+
+            let run = count.take().unwrap_or(1) as i32;
+            let state = match c {
+                'b' | '.' => Some(0),
+                'o' => Some(RLE_ALIVE as u32),
+                'A'..='X' => {
+                    let state = c as u32 - 'A' as u32 + 1;
+                    Some(state + 24*prefix.take().unwrap_or(0))
+                }
+                'p'..='y' if prefix.is_none() => {
+                    prefix = Some(c as u32 - 'p' as u32 + 1);
+                    // the count belongs to the whole token.
+                    count = Some(run as u32);
+                    continue;
+                }
+                '$' => { pos = ivec3(0, pos.y + run, pos.z); None }
+                '/' => { pos = ivec3(0, 0, pos.z + run); None }
+                '!' => break 'body,
+                _ if c.is_whitespace() => { count = Some(run as u32); continue; }
+                _ => return Err(RleError::InvalidToken(c)),
+            };
+
+            if let Some(state) = state {
+                if state > u8::MAX as u32 {
+                    return Err(RleError::StateOutOfRange(state));
+                }
+                if state != 0 {
+                    for x in pos.x..pos.x + run {
+                        let cell = ivec3(x, pos.y, pos.z);
+                        if cell.cmpge(size).any() {
+                            return Err(RleError::CellOutOfBounds(cell));
+                        }
+                        cells.push((cell, state as u8));
+                    }
+                }
+                pos.x += run;
+            }
+        }
+    }
+
+    if prefix.is_some() {
+        return Err(RleError::InvalidToken('!'));
+    }
+    Ok(cells)
+}
+
+fn state_token(state: u8) -> String {
+    match state {
+        0 => ".".into(),
+        1..=24 => ((b'A' + state - 1) as char).to_string(),
+        _ => {
+            let prefix = (state - 1)/24;
+            let letter = (state - 1)%24;
+            format!("{}{}", (b'p' + prefix - 1) as char, (b'A' + letter) as char)
+        }
+    }
+}
+
+// writes the cells relative to their bounding box. dead cells are skipped.
+#[allow(dead_code)]
+pub fn write_rle3<W: Write>(w: &mut W, cells: &[(IVec3, u8)]) -> io::Result<()> {
+    let live = || cells.iter().filter(|(_, state)| *state != 0);
+    let min = live().fold(IVec3::splat(i32::MAX), |min, (pos, _)| min.min(*pos));
+    let max = live().fold(IVec3::splat(i32::MIN), |max, (pos, _)| max.max(*pos));
+    if live().next().is_none() {
+        return writeln!(w, "x = 1, y = 1, z = 1\n!");
+    }
+
+    let size = max - min + IVec3::ONE;
+    let mut grid = vec![0u8; (size.x*size.y*size.z) as usize];
+    for (pos, state) in live() {
+        let pos = *pos - min;
+        grid[(pos.x + pos.y*size.x + pos.z*size.x*size.y) as usize] = *state;
+    }
+
+    // runs of (count, token). trailing dead cells in a row are dropped, and
+    // consecutive row/plane ends are merged.
+    fn push(runs: &mut Vec<(usize, String)>, token: String) {
+        match runs.last_mut() {
+            Some((count, last)) if *last == token => *count += 1,
+            _ => runs.push((1, token)),
+        }
+    }
+    // empty rows at the end of a plane don't need a `$`.
+    fn trim_rows(runs: &mut Vec<(usize, String)>) {
+        if matches!(runs.last(), Some((_, token)) if token == "$") {
+            runs.pop();
+        }
+    }
+
+    let mut runs = vec![];
+    for z in 0..size.z {
+        if z > 0 {
+            trim_rows(&mut runs);
+            push(&mut runs, "/".into());
+        }
+        for y in 0..size.y {
+            if y > 0 {
+                push(&mut runs, "$".into());
+            }
+            let row = &grid[((y + z*size.y)*size.x) as usize..][..size.x as usize];
+            let length = row.iter().rposition(|state| *state != 0).map_or(0, |end| end + 1);
+            for state in &row[..length] {
+                push(&mut runs, state_token(*state));
+            }
+        }
+    }
+    trim_rows(&mut runs);
+
+    writeln!(w, "x = {}, y = {}, z = {}", size.x, size.y, size.z)?;
+    let mut line = String::new();
+    for (count, token) in runs {
+        let run = if count > 1 { format!("{}{}", count, token) } else { token };
+        if line.len() + run.len() > 70 {
+            writeln!(w, "{}", line)?;
+            line.clear();
+        }
+        line.push_str(&run);
+    }
+    writeln!(w, "{}!", line)
+}
+
+
+/*
+    ascii patterns, for writing small ones inline:
+        - one row per line, `#` is alive (RLE_ALIVE), anything else is dead.
+          rows can have different lengths.
+        - planes (z) are separated by one or more blank lines.
+        - the common indentation of all rows is removed, so the pattern can be
+          indented in a string literal.
+*/
+
+// returns the live cells, relative to the pattern's corner.
+pub fn from_ascii(text: &str) -> Vec<(IVec3, u8)> {
+    let indent = text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.chars().take_while(|c| c.is_whitespace()).count())
+        .min()
+        .unwrap_or(0);
+
+    let mut cells = vec![];
+    let mut pos = IVec3::ZERO;
+    let mut in_plane = false;
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            if in_plane {
+                pos = ivec3(0, 0, pos.z + 1);
+                in_plane = false;
+            }
+            continue;
+        }
+
+        for (x, c) in line.chars().skip(indent).enumerate() {
+            if c == '#' {
+                cells.push((ivec3(x as i32, pos.y, pos.z), RLE_ALIVE));
+            }
+        }
+        pos.y += 1;
+        in_plane = true;
+    }
+    cells
+}
+
+
+/*
+    magicavoxel .vox models:
+        - "VOX " magic, version: u32, then chunks: id: [u8; 4], content size:
+          u32, children size: u32. all little endian.
+        - the MAIN chunk's children are a SIZE (x, y, z: u32) & XYZI (count:
+          u32, then (x, y, z, color index): [u8; 4] per voxel) pair per model.
+          other chunks (palette, scene graph, ...) are skipped.
+        - only the first model is loaded.
+        - the color index is used as the cell value.
+        - magicavoxel's z is up, ours is y, so y & z are swapped.
+*/
+
+#[derive(Debug)]
+pub enum VoxError {
+    Io(io::Error),
+    NotVox,
+    // a SIZE chunk without its XYZI chunk, or no model at all.
+    MissingModel,
+    VoxelOutOfBounds(IVec3),
+    TooLarge { size: IVec3, bounds: IVec3 },
+}
+
+impl std::fmt::Display for VoxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            VoxError::Io(error) =>
+                write!(f, "io error: {}", error),
+            VoxError::NotVox =>
+                write!(f, "not a magicavoxel .vox file"),
+            VoxError::MissingModel =>
+                write!(f, "the file has no (complete) model"),
+            VoxError::VoxelOutOfBounds(pos) =>
+                write!(f, "voxel at {} is outside the model's size", pos),
+            VoxError::TooLarge { size, bounds } =>
+                write!(f, "the model is {} large, but the bounds are only {}", size, bounds),
+        }
+    }
+}
+
+impl std::error::Error for VoxError {}
+
+impl From<io::Error> for VoxError {
+    fn from(error: io::Error) -> Self {
+        VoxError::Io(error)
+    }
+}
+
+
+// returns the voxels, relative to the model's corner.
+#[allow(dead_code)]
+pub fn load_vox<P: AsRef<Path>>(path: P) -> Result<Vec<(IVec3, u8)>, VoxError> {
+    let bytes = std::fs::read(path)?;
+    parse_vox(&bytes)
+}
+
+fn parse_vox(bytes: &[u8]) -> Result<Vec<(IVec3, u8)>, VoxError> {
+    fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, VoxError> {
+        let slice = bytes.get(offset..offset + 4)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        Ok(u32::from_le_bytes(slice.try_into().unwrap()))
+    }
+
+    if bytes.get(..4) != Some(b"VOX ") {
+        return Err(VoxError::NotVox);
+    }
+
+    // the chunks are walked flat, MAIN's children come right after its
+    // (empty) content.
+    let mut offset = 8;
+    let mut size: Option<IVec3> = None;
+    while offset + 12 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let content_size = u32_at(bytes, offset + 4)? as usize;
+        let content = offset + 12;
+
+        match id {
+            b"MAIN" => {
+                offset = content + content_size;
+                continue;
+            }
+            b"SIZE" => {
+                // y & z swapped, see above.
+                size = Some(ivec3(
+                    u32_at(bytes, content)? as i32,
+                    u32_at(bytes, content + 8)? as i32,
+                    u32_at(bytes, content + 4)? as i32));
+            }
+            b"XYZI" => {
+                let size = size.ok_or(VoxError::MissingModel)?;
+                let count = u32_at(bytes, content)? as usize;
+                let voxels = bytes.get(content + 4..content + 4 + 4*count)
+                    .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+
+                let mut cells = Vec::with_capacity(count);
+                for voxel in voxels.chunks_exact(4) {
+                    let pos = ivec3(voxel[0] as i32, voxel[2] as i32, voxel[1] as i32);
+                    if pos.cmpge(size).any() {
+                        return Err(VoxError::VoxelOutOfBounds(pos));
+                    }
+                    cells.push((pos, voxel[3]));
+                }
+                return Ok(cells);
+            }
+            _ => (),
+        }
+        offset = content + content_size + u32_at(bytes, offset + 8)? as usize;
+    }
+    Err(VoxError::MissingModel)
+}
+
+// where to stamp `cells` so they're centered in `bounds`. errors if they
+// don't fit.
+pub fn centered_origin(cells: &[(IVec3, u8)], bounds: IVec3) -> Result<IVec3, VoxError> {
+    let size = cells.iter()
+        .fold(IVec3::ZERO, |size, (pos, _)| size.max(*pos + IVec3::ONE));
+    if size.cmpgt(bounds).any() {
+        return Err(VoxError::TooLarge { size, bounds });
+    }
+    Ok((bounds - size)/2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut cells: Vec<(IVec3, u8)>) -> Vec<(IVec3, u8)> {
+        cells.sort_by_key(|(pos, _)| (pos.z, pos.y, pos.x));
+        cells
+    }
+
+    #[test]
+    fn ascii_layers() {
+        let cells = from_ascii("
+            ##
+            .#
+
+            #.
+
+
+            .#
+        ");
+        let expected = vec![
+            (ivec3(0, 0, 0), RLE_ALIVE),
+            (ivec3(1, 0, 0), RLE_ALIVE),
+            (ivec3(1, 1, 0), RLE_ALIVE),
+            (ivec3(0, 0, 1), RLE_ALIVE),
+            // several blank lines are still one plane break.
+            (ivec3(1, 0, 2), RLE_ALIVE),
+        ];
+        assert_eq!(sorted(cells), expected);
+    }
+
+    #[test]
+    fn ascii_ragged_rows() {
+        let cells = from_ascii("#\n..#\n\n.#..#\n#");
+        let expected = vec![
+            (ivec3(0, 0, 0), RLE_ALIVE),
+            (ivec3(2, 1, 0), RLE_ALIVE),
+            (ivec3(1, 0, 1), RLE_ALIVE),
+            (ivec3(4, 0, 1), RLE_ALIVE),
+            (ivec3(0, 1, 1), RLE_ALIVE),
+        ];
+        assert_eq!(sorted(cells), expected);
+    }
+
+    #[test]
+    fn ascii_keeps_relative_indentation() {
+        let cells = from_ascii("    #\n      #");
+        assert_eq!(sorted(cells), vec![(ivec3(0, 0, 0), RLE_ALIVE), (ivec3(2, 1, 0), RLE_ALIVE)]);
+        assert!(from_ascii("").is_empty());
+        assert!(from_ascii("..\n\n..").is_empty());
+    }
+}
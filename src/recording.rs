@@ -0,0 +1,219 @@
+/*
+    how it works:
+        - the framebuffer can't be read back on this bevy revision (see
+          ScreenshotEvent), so a RecordSession draws its own frames: the cell
+          instances are projected straight along z (x right, y up), far cells
+          first, so the near ones cover them. far cells are darkened a bit, so
+          the depth still reads.
+        - screenshots (see ScreenshotEvent) are drawn the same way, as a
+          single png.
+        - frames are raw rgba8 and are kept in memory. or, with a spill file,
+          appended to that file right away, which keeps the memory flat for
+          long runs.
+        - finish encodes all frames into one apng (which is still a valid png,
+          viewers without apng support show the first frame).
+*/
+
+use bevy::math::{IVec3, Vec3};
+
+use crate::{cell_renderer::InstanceData, utils};
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+
+// the longer side of a frame, in pixels. cells are whole pixels, so frames
+// can be a bit smaller.
+const FRAME_SIZE: i32 = 512;
+const BACKGROUND: [u8; 4] = [166, 230, 245, 255];
+// how dark the farthest cells get.
+const DEPTH_SHADE: f32 = 0.5;
+
+#[derive(Debug)]
+pub enum RecordError {
+    Io(io::Error),
+    Png(png::EncodingError),
+    NoFrames,
+}
+
+impl std::fmt::Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RecordError::Io(error) =>
+                write!(f, "io error: {}", error),
+            RecordError::Png(error) =>
+                write!(f, "png error: {}", error),
+            RecordError::NoFrames =>
+                write!(f, "nothing was recorded"),
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+impl From<io::Error> for RecordError {
+    fn from(error: io::Error) -> Self {
+        RecordError::Io(error)
+    }
+}
+
+impl From<png::EncodingError> for RecordError {
+    fn from(error: png::EncodingError) -> Self {
+        RecordError::Png(error)
+    }
+}
+
+
+pub struct RecordSession {
+    pub fps: u16,
+    // bounds at the start. the frame size can't change mid animation, so
+    // cells outside of these are cut off if the bounds grow.
+    bounds: IVec3,
+    cell_size: i32,
+    width:  u32,
+    height: u32,
+    frames: Vec<Vec<u8>>,
+    spill: Option<(PathBuf, BufWriter<File>)>,
+    frame_count: u32,
+}
+
+impl RecordSession {
+    // `spill`: stream the frames to this file instead of keeping them.
+    pub fn new(bounds: IVec3, fps: u16, spill: Option<PathBuf>) -> io::Result<Self> {
+        let cell_size = (FRAME_SIZE / bounds.x.max(bounds.y).max(1)).max(1);
+        let spill = match spill {
+            Some(path) => {
+                let file = BufWriter::new(File::create(&path)?);
+                Some((path, file))
+            }
+            None => None,
+        };
+        Ok(RecordSession {
+            fps: fps.max(1),
+            bounds,
+            cell_size,
+            width:  (bounds.x.max(1) * cell_size) as u32,
+            height: (bounds.y.max(1) * cell_size) as u32,
+            frames: vec![],
+            spill,
+            frame_count: 0,
+        })
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    pub fn capture(&mut self, instances: &[InstanceData]) -> io::Result<()> {
+        let frame = self.draw(instances);
+        match &mut self.spill {
+            Some((_, file)) => file.write_all(&frame)?,
+            None => self.frames.push(frame),
+        }
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    // the same drawing as a recording's frames, as a png.
+    pub fn screenshot(bounds: IVec3, instances: &[InstanceData]) -> Result<Vec<u8>, RecordError> {
+        let session = RecordSession::new(bounds, 1, None)?;
+        let frame = session.draw(instances);
+        Ok(encode_png(session.width, session.height, &frame)?)
+    }
+
+    fn draw(&self, instances: &[InstanceData]) -> Vec<u8> {
+        let (width, height) = (self.width as i32, self.height as i32);
+        let mut frame = BACKGROUND.repeat((width * height) as usize);
+
+        // the instances are relative to the center, see Sims' update.
+        let offset = utils::center(self.bounds).as_vec3() + Vec3::splat(0.5);
+        let depth  = (self.bounds.z - 1).max(1) as f32;
+
+        let mut order: Vec<&InstanceData> = instances.iter().collect();
+        order.sort_by(|a, b| a.position.z.partial_cmp(&b.position.z).unwrap());
+
+        for instance in order {
+            let pos = instance.position + offset;
+            let shade = 1.0 - DEPTH_SHADE * (1.0 - (pos.z - 0.5) / depth).clamp(0.0, 1.0);
+            let mut color = [255; 4];
+            for (channel, value) in color.iter_mut().zip(&instance.color[..3]) {
+                *channel = (value * shade * 255.0).clamp(0.0, 255.0) as u8;
+            }
+
+            let size = (instance.scale * self.cell_size as f32).round().max(1.0) as i32;
+            let center_x = (pos.x * self.cell_size as f32) as i32;
+            // images go top down.
+            let center_y = height - (pos.y * self.cell_size as f32) as i32;
+            let x0 = (center_x - size/2).max(0);
+            let y0 = (center_y - size/2).max(0);
+            let x1 = (center_x - size/2 + size).min(width);
+            let y1 = (center_y - size/2 + size).min(height);
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let i = 4*(x + y*width) as usize;
+                    frame[i..i + 4].copy_from_slice(&color);
+                }
+            }
+        }
+        frame
+    }
+
+    // encodes everything as an apng at `path`. removes the spill file.
+    pub fn finish<P: AsRef<Path>>(self, path: P) -> Result<u32, RecordError> {
+        if self.frame_count == 0 {
+            if let Some((spill_path, file)) = self.spill {
+                drop(file);
+                std::fs::remove_file(spill_path)?;
+            }
+            return Err(RecordError::NoFrames);
+        }
+
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        // 0 plays = loop forever.
+        encoder.set_animated(self.frame_count, 0)?;
+        encoder.set_frame_delay(1, self.fps)?;
+        let mut writer = encoder.write_header()?;
+
+        match self.spill {
+            Some((spill_path, file)) => {
+                // flushes the last frames.
+                file.into_inner().map_err(|error| error.into_error())?;
+
+                let mut file = BufReader::new(File::open(&spill_path)?);
+                let mut frame = vec![0; 4 * (self.width * self.height) as usize];
+                for _ in 0..self.frame_count {
+                    file.read_exact(&mut frame)?;
+                    writer.write_image_data(&frame)?;
+                }
+                drop(file);
+                std::fs::remove_file(spill_path)?;
+            }
+            None => {
+                for frame in self.frames.iter() {
+                    writer.write_image_data(frame)?;
+                }
+            }
+        }
+        writer.finish()?;
+        Ok(self.frame_count)
+    }
+}
+
+
+// a single rgba8 frame as a png.
+pub fn encode_png(width: u32, height: u32, frame: &[u8]) -> Result<Vec<u8>, png::EncodingError> {
+    let mut bytes = vec![];
+    {
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(frame)?;
+        // the writer adds IEND when it's dropped.
+    }
+    Ok(bytes)
+}
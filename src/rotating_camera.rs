@@ -1,5 +1,10 @@
 use bevy::math::{vec3, Quat};
 use bevy::prelude::*;
+use bevy::render::camera::{DepthCalculation, ScalingMode};
+use bevy_egui::EguiContext;
+use serde::{Deserialize, Serialize};
+
+use std::path::Path;
 
 #[derive(Component)]
 pub struct RotatingCamera {
@@ -8,8 +13,20 @@ pub struct RotatingCamera {
     pub speed: f32,
     pub dist: f32,
     pub center: Vec3,
+    // of the perspective projection, kept while orthographic. the
+    // orthographic view is as high as the perspective one at `center`.
+    pub fov: f32,
 }
 
+// world space box the camera should keep in view. `None` holds position.
+#[derive(Default)]
+pub struct FrameTarget(pub Option<(Vec3, Vec3)>);
+
+// extra space around the frame target.
+const FRAME_MARGIN: f32 = 1.2;
+// how fast the camera moves to the frame target, per tick.
+const FRAME_SPEED: f32 = 0.05;
+
 impl Default for RotatingCamera {
     fn default() -> Self {
         Self {
@@ -18,25 +35,174 @@ impl Default for RotatingCamera {
             speed: 0.01f32,
             dist: 150f32,
             center: vec3(0.0, 0.0, 0.0),
+            fov: std::f32::consts::FRAC_PI_4,
+        }
+    }
+}
+
+// a saved view: what update_tick builds the transform from.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct CameraBookmark {
+    pub rotation: f32,
+    pub dist: f32,
+    pub center: [f32; 3],
+}
+
+// shift + 1..9 stores the view, 1..9 goes back to it. saved to
+// BOOKMARKS_PATH on every store, so they survive restarts.
+#[derive(Default, Serialize, Deserialize)]
+pub struct CameraBookmarks(pub [Option<CameraBookmark>; 9]);
+
+pub const BOOKMARKS_PATH: &str = "camera_bookmarks.json";
+
+const BOOKMARK_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1, KeyCode::Key2, KeyCode::Key3,
+    KeyCode::Key4, KeyCode::Key5, KeyCode::Key6,
+    KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+];
+
+impl CameraBookmarks {
+    // no file means no bookmarks yet. a broken file is reported and ignored
+    // (and overwritten by the next store).
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => return CameraBookmarks::default(),
+        };
+        match serde_json::from_str(&text) {
+            Ok(bookmarks) => bookmarks,
+            Err(error) => {
+                eprintln!("{}: {}", path.as_ref().display(), error);
+                CameraBookmarks::default()
+            }
         }
     }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, text)
+    }
 }
 
 pub struct RotatingCameraPlugin;
 impl Plugin for RotatingCameraPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(update_tick);
+        app
+        .init_resource::<FrameTarget>()
+        .insert_resource(CameraBookmarks::load(BOOKMARKS_PATH))
+        .add_system(update_tick)
+        .add_system(bookmark_input)
+        .add_system(toggle_projection);
+    }
+}
+
+pub fn bookmark_input(
+    mut cameras: Query<&mut RotatingCamera>,
+    mut bookmarks: ResMut<CameraBookmarks>,
+    keys: Res<Input<KeyCode>>,
+    mut egui_context: ResMut<EguiContext>,
+) {
+    // ctrl + 1..3 switches the sim, see sims.rs. digits typed into a text
+    // field aren't bookmarks either.
+    if keys.pressed(KeyCode::LControl) || keys.pressed(KeyCode::RControl)
+    || egui_context.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    let store = keys.pressed(KeyCode::LShift) || keys.pressed(KeyCode::RShift);
+
+    for (slot, key) in BOOKMARK_KEYS.iter().enumerate() {
+        if !keys.just_pressed(*key) {
+            continue;
+        }
+        for mut camera in cameras.iter_mut() {
+            if store {
+                bookmarks.0[slot] = Some(CameraBookmark {
+                    rotation: camera.rotation,
+                    dist: camera.dist,
+                    center: camera.center.into(),
+                });
+                if let Err(error) = bookmarks.save(BOOKMARKS_PATH) {
+                    eprintln!("couldn't save the camera bookmarks: {}", error);
+                }
+            }
+            else if let Some(bookmark) = bookmarks.0[slot] {
+                camera.rotation = bookmark.rotation;
+                camera.dist = bookmark.dist;
+                camera.center = bookmark.center.into();
+            }
+        }
+    }
+}
+
+// o swaps the projection component. bevy's camera systems pick up the new
+// one, as it counts as changed.
+pub fn toggle_projection(
+    mut commands: Commands,
+    mut cameras: Query<(Entity, &mut RotatingCamera, Option<&PerspectiveProjection>)>,
+    keys: Res<Input<KeyCode>>,
+    mut egui_context: ResMut<EguiContext>,
+) {
+    if !keys.just_pressed(KeyCode::O) || egui_context.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    for (entity, mut camera, perspective) in cameras.iter_mut() {
+        match perspective {
+            Some(perspective) => {
+                camera.fov = perspective.fov;
+                commands.entity(entity)
+                    .remove::<PerspectiveProjection>()
+                    .insert(OrthographicProjection {
+                        scaling_mode: ScalingMode::FixedVertical,
+                        depth_calculation: DepthCalculation::Distance,
+                        scale: orthographic_scale(&camera),
+                        ..Default::default()
+                    });
+            }
+            None => {
+                commands.entity(entity)
+                    .remove::<OrthographicProjection>()
+                    .insert(PerspectiveProjection {
+                        fov: camera.fov,
+                        ..Default::default()
+                    });
+            }
+        }
     }
 }
 
+// half the height of the perspective view at the camera's center.
+fn orthographic_scale(camera: &RotatingCamera) -> f32 {
+    camera.dist * (camera.fov / 2.0).tan()
+}
+
 pub fn update_tick(
-    mut cameras: Query<(&mut RotatingCamera, &mut Transform)>,
+    mut cameras: Query<(&mut RotatingCamera, &mut Transform, Option<&PerspectiveProjection>, Option<&mut OrthographicProjection>)>,
+    frame_target: Res<FrameTarget>,
 ) {
-    for (mut camera, mut transform) in cameras.iter_mut() {
+    for (mut camera, mut transform, projection, orthographic) in cameras.iter_mut() {
+        if let Some((min, max)) = frame_target.0 {
+            // fit the bounding sphere of the box into the vertical fov.
+            let fov = projection.map_or(camera.fov, |p| p.fov);
+            let radius = (max - min).length() / 2.0;
+            let dist = FRAME_MARGIN * radius / (fov / 2.0).sin();
+            let center = (min + max) / 2.0;
+            camera.center = camera.center.lerp(center, FRAME_SPEED);
+            camera.dist += (dist - camera.dist) * FRAME_SPEED;
+        }
+
         let delta = 1.0f32;
         camera.rotation += delta * camera.speed;
         let rotation = Quat::from_axis_angle(Vec3::Y, camera.rotation);
         transform.translation = camera.center + (rotation * Vec3::Z * camera.dist);
         transform.look_at(camera.center, Vec3::Y);
+
+        // so framing & bookmarks still zoom. only on change, the camera
+        // systems recompute the projection on every change.
+        if let Some(mut orthographic) = orthographic {
+            let scale = orthographic_scale(&camera);
+            if orthographic.scale != scale {
+                orthographic.scale = scale;
+            }
+        }
     }
 }
@@ -1,50 +1,196 @@
-use bevy::prelude::Color;
+use bevy::{math::{ivec3, BVec3, IVec3, Vec3, Vec4}, prelude::Color};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::ops::RangeInclusive;
+use std::path::Path;
 
-use crate::{neighbours::NeighbourMethod, utils};
+use crate::{neighbours::{NeighbourMethod, MAX_NEIGHBOURS, MAX_RADIUS}, palette::Palette, utils::{self, NoiseKind, SeedShape}};
 
 #[derive(Clone, Copy, PartialEq)]
-pub struct Value ([bool; 27]);
+pub struct Value ([bool; MAX_NEIGHBOURS + 1]);
 
 impl Value {
-    pub fn new(indices: &[u8]) -> Self {
-        let mut result = Value([false; 27]);
+    pub fn new(indices: &[u16]) -> Self {
+        let mut result = Value([false; MAX_NEIGHBOURS + 1]);
         for index in indices {
             result.0[*index as usize] = true;
         }
         result
     }
 
-    pub fn from_range(indices: RangeInclusive<u8>) -> Self {
-        let mut result = Value([false; 27]);
+    pub fn from_range(indices: RangeInclusive<u16>) -> Self {
+        let mut result = Value([false; MAX_NEIGHBOURS + 1]);
         for index in indices {
             result.0[index as usize] = true;
         }
         result
     }
 
-    #[allow(dead_code)]
-    pub fn in_range(&self, value: u8) -> bool {
-        self.0[value as usize]
+    // weighted neighbour counts can be larger than MAX_NEIGHBOURS.
+    pub fn in_range(&self, value: u16) -> bool {
+        *self.0.get(value as usize).unwrap_or(&false)
     }
 
-    pub fn in_range_incorrect(&self, value: u8) -> bool {
-        *self.0.get(value as usize).unwrap_or(&false)
+    pub fn indices(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..self.0.len() as u16).filter(move |index| self.0[*index as usize])
+    }
+
+    // adds the count after the highest one (1 if there is none), up to `max`.
+    pub fn widen(&mut self, max: u16) {
+        let next = self.indices().last().map_or(1, |last| last + 1);
+        if next <= max.min(MAX_NEIGHBOURS as u16) {
+            self.0[next as usize] = true;
+        }
+    }
+
+    pub fn toggle(&mut self, index: u16) {
+        if let Some(set) = self.0.get_mut(index as usize) {
+            *set = !*set;
+        }
+    }
+
+    // removes the highest count.
+    pub fn narrow(&mut self) {
+        if let Some(last) = self.indices().last() {
+            self.0[last as usize] = false;
+        }
+    }
+
+    // "4,6,8-10" style, see Rule::from_rulestring.
+    fn parse(text: &str) -> Result<Self, RuleParseError> {
+        fn parse_index(text: &str) -> Result<u16, RuleParseError> {
+            let index = text.trim().parse::<u16>()
+                .map_err(|_| RuleParseError::InvalidNumber(text.into()))?;
+            if index as usize > MAX_NEIGHBOURS {
+                return Err(RuleParseError::NeighbourCountOutOfRange(index));
+            }
+            Ok(index)
+        }
+
+        let mut result = Value([false; MAX_NEIGHBOURS + 1]);
+        for item in text.split(',').filter(|item| !item.trim().is_empty()) {
+            match item.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (parse_index(start)?, parse_index(end)?);
+                    if start > end {
+                        return Err(RuleParseError::InvalidNumber(item.into()));
+                    }
+                    (start..=end).for_each(|index| result.0[index as usize] = true);
+                }
+                None => result.0[parse_index(item)? as usize] = true,
+            }
+        }
+        Ok(result)
+    }
+
+    fn to_rulestring(&self) -> String {
+        let mut ranges: Vec<(u16, u16)> = vec![];
+        for index in self.indices() {
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == index => *end = index,
+                _ => ranges.push((index, index)),
+            }
+        }
+
+        ranges.iter()
+            .map(|(start, end)| {
+                if start == end { format!("{}", start) }
+                else { format!("{}-{}", start, end) }
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RuleParseError {
+    // expected "B<birth>/S<survival>/<states>/<M|N>".
+    InvalidFormat,
+    InvalidNumber(String),
+    NeighbourCountOutOfRange(u16),
+    InvalidStates(String),
+    UnknownNeighbourMethod(String),
+}
+
+impl std::fmt::Display for RuleParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RuleParseError::InvalidFormat =>
+                write!(f, "expected B<birth>/S<survival>/<states>/<M|N>"),
+            RuleParseError::InvalidNumber(text) =>
+                write!(f, "invalid number or range: {:?}", text),
+            RuleParseError::NeighbourCountOutOfRange(count) =>
+                write!(f, "neighbour count {} is out of range", count),
+            RuleParseError::InvalidStates(text) =>
+                write!(f, "invalid state count: {:?}", text),
+            RuleParseError::UnknownNeighbourMethod(text) =>
+                write!(f, "unknown neighbour method: {:?}", text),
+        }
     }
 }
 
+impl std::error::Error for RuleParseError {}
+
 
 #[allow(dead_code)]
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ColorMethod {
     Single,
     StateLerp,
     DistToCenter,
     Neighbour,
+    // neighbours relative to the most the neighbour method can have.
+    NeighbourDensity,
+    // fades from `start` (full state) to `end` (about to die).
+    StateGradient { start: Color, end: Color },
+    // x, y, z -> r, g, b.
+    PositionRgb,
+    // like NeighbourDensity, but the brightness goes up to 1 + HOT_CORE_GAIN
+    // in dense regions. only looks different with hdr colors.
+    HotCore,
+    // one color per value, see palette.rs.
+    Palette(Palette),
+    // the direction towards more live cells, x, y, z -> r, g, b. gray where
+    // it's flat. see BoundaryMode::density_gradient.
+    GradientFlow,
+    // generations since the cell was born, from c1 (just born) to c2
+    // (`fade` generations or older). so old, stable cells are one color and
+    // fresh growth another. sims that don't track births show every cell as
+    // just born.
+    BirthTime { fade: u32 },
 }
 
+pub const HOT_CORE_GAIN: f32 = 3.0;
+
 impl ColorMethod {
-    pub fn color(&self, c1: Color, c2: Color, states: u8, state: u8, neighbours: u8, dist_to_center: f32) -> Color {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorMethod::Single => "Single",
+            ColorMethod::StateLerp => "State Lerp",
+            ColorMethod::DistToCenter => "Distance to Center",
+            ColorMethod::Neighbour => "Neighbors",
+            ColorMethod::NeighbourDensity => "Neighbor Density",
+            ColorMethod::StateGradient { .. } => "State Gradient",
+            ColorMethod::PositionRgb => "Position RGB",
+            ColorMethod::HotCore => "Hot Core",
+            ColorMethod::Palette(_) => "Palette",
+            ColorMethod::GradientFlow => "Gradient Flow",
+            ColorMethod::BirthTime { .. } => "Birth Time",
+        }
+    }
+
+    // `pos` is relative to the bounds, 0..1 on each axis. `gradient` is only
+    // used if needs_gradient, callers can skip computing it otherwise. same
+    // for `age` and needs_age, it's generations since the cell was born.
+    #[allow(clippy::too_many_arguments)]
+    pub fn color(&self,
+        c1: Color, c2: Color, rule: &Rule,
+        state: u8, neighbours: u16, dist_to_center: f32, pos: Vec3,
+        gradient: Vec3, age: u32,
+    ) -> Color {
+        let states = rule.states;
         match self {
             ColorMethod::Single => c1,
             ColorMethod::StateLerp => {
@@ -58,14 +204,829 @@ impl ColorMethod {
                 let dt = neighbours as f32 / 26f32;
                 utils::lerp_color(c1, c2, dt)
             }
+            ColorMethod::NeighbourDensity => {
+                let dt = neighbours as f32 / rule.max_neighbours() as f32;
+                utils::lerp_color(c1, c2, dt)
+            }
+            ColorMethod::StateGradient { start, end } => {
+                // states == 1 has no decay states to fade through.
+                let dt =
+                    if states > 1 { states.saturating_sub(state) as f32 / (states - 1) as f32 }
+                    else { 0.0 };
+                utils::lerp_color(*start, *end, dt)
+            }
+            ColorMethod::PositionRgb => {
+                let pos = pos.clamp(Vec3::ZERO, Vec3::ONE);
+                Color::rgb(pos.x, pos.y, pos.z)
+            }
+            ColorMethod::HotCore => {
+                let dt = neighbours as f32 / rule.max_neighbours().max(1) as f32;
+                let color: Vec4 = utils::lerp_color(c1, c2, dt).into();
+                let brightness = 1.0 + HOT_CORE_GAIN*dt.clamp(0.0, 1.0);
+                (color * Vec4::new(brightness, brightness, brightness, 1.0)).into()
+            }
+            ColorMethod::Palette(palette) => palette.color(state),
+            ColorMethod::GradientFlow => {
+                let direction = 0.5*gradient.normalize_or_zero() + Vec3::splat(0.5);
+                Color::rgb(direction.x, direction.y, direction.z)
+            }
+            ColorMethod::BirthTime { fade } => {
+                let dt = (age as f32 / (*fade).max(1) as f32).min(1.0);
+                utils::lerp_color(c1, c2, dt)
+            }
         }
     }
+
+    pub fn needs_gradient(&self) -> bool {
+        matches!(self, ColorMethod::GradientFlow)
+    }
+
+    // sims only track births while this is true, see Sim::set_track_births.
+    pub fn needs_age(&self) -> bool {
+        matches!(self, ColorMethod::BirthTime { .. })
+    }
+
+    // true if `color` can return components above 1.0.
+    pub fn is_hdr(&self) -> bool {
+        matches!(self, ColorMethod::HotCore)
+    }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BoundaryMode {
+    // toroidal, positions past the border continue on the other side.
+    Wrap,
+    // positions past the border are moved back onto the border.
+    Clamp,
+    // everything past the border is dead.
+    Dead,
+    // wraps on the axes that are set, the others are dead past the border.
+    // eg: x & y set makes a terrarium with a hard floor & ceiling in z.
+    // [x, y, z] in rule files.
+    #[serde(serialize_with = "serialize_axes", deserialize_with = "deserialize_axes")]
+    WrapAxes(BVec3),
+}
+
+impl BoundaryMode {
+    // maps a (possibly out of bounds) position to the cell it refers to.
+    // returns None if there is no such cell.
+    pub fn resolve(&self, pos: IVec3, bounds: IVec3) -> Option<IVec3> {
+        match self {
+            BoundaryMode::Wrap => Some(utils::wrap(pos, bounds)),
+            BoundaryMode::Clamp => Some(pos.max(IVec3::ZERO).min(bounds - IVec3::ONE)),
+            BoundaryMode::Dead => {
+                if utils::is_in_bounds(pos, bounds) { Some(pos) } else { None }
+            }
+            BoundaryMode::WrapAxes(axes) => {
+                let pos = utils::wrap_axes(pos, bounds, *axes);
+                if utils::is_in_bounds(pos, bounds) { Some(pos) } else { None }
+            }
+        }
+    }
+
+    // for the distance based color methods.
+    pub fn dist_to_center(&self, pos: IVec3, bounds: IVec3) -> f32 {
+        match self {
+            BoundaryMode::Wrap => utils::dist_to_center_wrapped(pos, bounds),
+            _ => utils::dist_to_center(pos, bounds),
+        }
+    }
+
+    // central differences of the liveness of the six axis neighbors, so each
+    // component is -1, 0 or 1. neighbors past a dead border count as dead.
+    pub fn density_gradient<F: Fn(usize) -> bool>(&self, pos: IVec3, bounds: IVec3, is_live: F) -> Vec3 {
+        let live = |offset: IVec3| match self.resolve(pos + offset, bounds) {
+            Some(pos) => is_live(utils::pos_to_index(pos, bounds)) as i32 as f32,
+            None => 0.0,
+        };
+        Vec3::new(
+            live(IVec3::X) - live(-IVec3::X),
+            live(IVec3::Y) - live(-IVec3::Y),
+            live(IVec3::Z) - live(-IVec3::Z))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BoundaryMode::Wrap => "Wrap",
+            BoundaryMode::Clamp => "Clamp",
+            BoundaryMode::Dead => "Dead",
+            BoundaryMode::WrapAxes(_) => "Wrap per Axis",
+        }
+    }
+}
+
+// what happens to a full cell that fails survival.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeathMode {
+    // counts down through the states, one per generation.
+    Decay,
+    // straight to dead, so a rule with more states is binary, apart from
+    // the colors.
+    Instant,
+}
+
+impl DeathMode {
+    // value of a live cell that is dying (not full or failed survival).
+    pub fn next(&self, value: u8) -> u8 {
+        match self {
+            DeathMode::Decay   => value - 1,
+            DeathMode::Instant => 0,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeathMode::Decay   => "Decay",
+            DeathMode::Instant => "Instant",
+        }
+    }
+}
+
+// mirror planes through the center, for spawning symmetric seeds and for
+// Rule::enforce_symmetry.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Symmetry {
+    // x = center.
+    MirrorX,
+    // x = center & y = center.
+    MirrorXY,
+    // all three axis planes, so every octant is the same.
+    Cubic,
+    // cubic + the diagonal planes (axes can be swapped).
+    Octahedral,
+}
+
+impl Symmetry {
+    // all images of `offset` (relative to the center), including itself.
+    pub fn images(&self, offset: IVec3) -> Vec<IVec3> {
+        let flips = match self {
+            Symmetry::MirrorX  => ivec3(1, 0, 0),
+            Symmetry::MirrorXY => ivec3(1, 1, 0),
+            Symmetry::Cubic | Symmetry::Octahedral => ivec3(1, 1, 1),
+        };
+        let permutations: &[IVec3] =
+            if *self == Symmetry::Octahedral {
+                &[ivec3(0, 1, 2), ivec3(0, 2, 1), ivec3(1, 0, 2),
+                  ivec3(1, 2, 0), ivec3(2, 0, 1), ivec3(2, 1, 0)]
+            }
+            else {
+                &[ivec3(0, 1, 2)]
+            };
+
+        let mut result = vec![];
+        for permutation in permutations {
+            let offset = ivec3(
+                offset[permutation.x as usize],
+                offset[permutation.y as usize],
+                offset[permutation.z as usize]);
+            for flip in 0..8 {
+                let flip = ivec3(flip & 1, (flip >> 1) & 1, (flip >> 2) & 1);
+                if flip.cmpgt(flips).any() {
+                    continue;
+                }
+                result.push(offset * (IVec3::ONE - 2*flip));
+            }
+        }
+        result
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Symmetry::MirrorX    => "Mirror X",
+            Symmetry::MirrorXY   => "Mirror XY",
+            Symmetry::Cubic      => "Cubic",
+            Symmetry::Octahedral => "Octahedral",
+        }
+    }
+}
+
+// in rule files, birth & survival use the rulestring syntax ("4,6,8-10"), and
+// the neighbour method is the last rulestring part ("M", "N2").
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Rule {
+    #[serde(rename = "survival", serialize_with = "serialize_value", deserialize_with = "deserialize_value")]
     pub survival_rule: Value,
+    #[serde(rename = "birth", serialize_with = "serialize_value", deserialize_with = "deserialize_value")]
     pub birth_rule: Value,
     pub states: u8,
+    #[serde(rename = "neighbours",
+        serialize_with = "serialize_neighbour_method",
+        deserialize_with = "deserialize_neighbour_method")]
     pub neighbour_method: NeighbourMethod,
+    // survival counts with this neighbourhood instead, if set. birth,
+    // transitions & everything else keep neighbour_method. this is an
+    // override on purpose, instead of separate birth & survival methods:
+    // neighbour_method stays the one neighbourhood of rulestrings, rule files
+    // & the other sims, and None means both count the same.
+    // only leddoo's atomic sim supports it (see Sim::supports); it keeps a
+    // second u16 counter per cell while this is set, so 5 instead of 3 bytes
+    // per cell, 32 MB more at MAX_BOUNDS. see with_survival_neighbours.
+    #[serde(rename = "survival_neighbours", default, skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_survival_neighbour_method",
+        deserialize_with = "deserialize_survival_neighbour_method")]
+    pub survival_neighbour_method: Option<NeighbourMethod>,
+    #[serde(default = "default_boundary_mode")]
+    pub boundary_mode: BoundaryMode,
+    // render dying cells smaller, by value/states. doesn't affect the sim.
+    #[serde(default)]
+    pub scale_by_state: bool,
+    // chance of each cell in the noise region to be seeded, 0..=1.
+    #[serde(default = "default_noise_density")]
+    pub noise_density: f32,
+    #[serde(default = "default_seed_shape")]
+    pub seed_shape: SeedShape,
+    #[serde(default = "default_noise_kind")]
+    pub noise_kind: NoiseKind,
+    // ignored with transitions. tantan's sims always decay.
+    #[serde(default = "default_death_mode")]
+    pub death_mode: DeathMode,
+    // totalistic rules: a full cell also counts itself. like any neighbor,
+    // only while it's full, so a decaying cell already lost its own count
+    // (a cell with count_self can't survive with 0 neighbors, it has 1).
+    #[serde(default)]
+    pub count_self: bool,
+    // after every update, each cell is or-ed with its mirror images (the
+    // highest value wins), so the grid stays symmetric around the center.
+    // that's not quite the rule anymore, but it's pretty. only leddoo's
+    // atomic sim supports it, see enforce_symmetry there.
+    #[serde(default)]
+    pub enforce_symmetry: Option<Symmetry>,
+    // replaces the whole update with reversible block steps, if set. only
+    // leddoo's atomic sim supports it.
+    #[serde(skip)]
+    pub margolus: Option<MargolusTable>,
+    // replaces birth & survival, if set. only the leddoo sims support it.
+    #[serde(skip)]
+    pub transitions: Option<TransitionTable>,
+}
+
+// nothing is born & nothing survives. the rest is what rule files default
+// to, so literals only need birth, survival, states & the neighbourhood:
+// Rule { .., ..Default::default() }.
+impl Default for Rule {
+    fn default() -> Self {
+        Rule {
+            survival_rule: Value::new(&[]),
+            birth_rule: Value::new(&[]),
+            states: 1,
+            neighbour_method: NeighbourMethod::Moore(1),
+            survival_neighbour_method: None,
+            boundary_mode: default_boundary_mode(),
+            scale_by_state: false,
+            noise_density: default_noise_density(),
+            seed_shape: default_seed_shape(),
+            noise_kind: default_noise_kind(),
+            death_mode: default_death_mode(),
+            count_self: false,
+            enforce_symmetry: None,
+            margolus: None,
+            transitions: None,
+        }
+    }
+}
+
+
+// the next state of a 2x2x2 block, for the block update of margolus
+// neighbourhoods: the grid is split into blocks (shifted by one cell on all
+// axes every other generation), and every block is replaced by its next
+// state. bit i of a state is the corner ((i>>0)&1, (i>>1)&1, (i>>2)&1), set if
+// it's full. a table that's a permutation is reversible, and only those can
+// be built, so every step can be undone with `previous`.
+#[derive(Clone, PartialEq)]
+pub struct MargolusTable {
+    next: [u8; 256],
+    previous: [u8; 256],
+}
+
+impl MargolusTable {
+    // None if two states have the same next state.
+    pub fn from_fn<F: Fn(u8) -> u8>(f: F) -> Option<Self> {
+        let mut next = [0; 256];
+        let mut previous = [0; 256];
+        let mut seen = [false; 256];
+        for block in 0..=255u8 {
+            let to = f(block);
+            if std::mem::replace(&mut seen[to as usize], true) {
+                return None;
+            }
+            next[block as usize] = to;
+            previous[to as usize] = block;
+        }
+        Some(MargolusTable { next, previous })
+    }
+
+    // a lattice gas: a lone particle flies through the block to the opposite
+    // corner, so everything moves diagonally. two particles that meet head on
+    // (opposite corners) leave along the next diagonal instead.
+    pub fn gas() -> Self {
+        // corner i is opposite to corner 7 - i, so the diagonals are i | 7-i.
+        let diagonal = |i: u8| (1u8 << i) | (1 << (7 - i));
+        MargolusTable::from_fn(|block| {
+            if let Some(i) = (0..4).find(|&i| block == diagonal(i)) {
+                return diagonal((i + 1) % 4);
+            }
+            (0..8).filter(|i| block & (1 << i) != 0).map(|i| 1 << (7 - i)).sum()
+        }).unwrap()
+    }
+
+    pub fn next(&self, block: u8) -> u8 {
+        self.next[block as usize]
+    }
+
+    pub fn previous(&self, block: u8) -> u8 {
+        self.previous[block as usize]
+    }
+}
+
+
+// next value for every (value, neighbours) pair. neighbours still only counts
+// cells in the full state (`states`), so eg: brian's brain is states = 2 with
+// 0 -> 2 for 2 neighbours, 2 -> 1, 1 -> 0.
+#[derive(Clone, PartialEq)]
+pub struct TransitionTable {
+    states: u8,
+    next: std::sync::Arc<Vec<u8>>,
+}
+
+impl TransitionTable {
+    pub fn from_fn<F: Fn(u8, u16) -> u8>(states: u8, f: F) -> Self {
+        let mut next = Vec::with_capacity((states as usize + 1)*(MAX_NEIGHBOURS + 1));
+        for value in 0..=states {
+            for neighbours in 0..=MAX_NEIGHBOURS as u16 {
+                next.push(f(value, neighbours).min(states));
+            }
+        }
+        TransitionTable { states, next: std::sync::Arc::new(next) }
+    }
+
+    // values & counts outside the table die.
+    pub fn next(&self, value: u8, neighbours: u16) -> u8 {
+        if value > self.states || neighbours as usize > MAX_NEIGHBOURS {
+            return 0;
+        }
+        self.next[value as usize*(MAX_NEIGHBOURS + 1) + neighbours as usize]
+    }
+}
+
+fn parse_neighbour_method(text: &str) -> Result<NeighbourMethod, RuleParseError> {
+    // "W<face>,<edge>,<corner>".
+    if let Some(weights) = text.strip_prefix('W') {
+        let weights = weights.split(',')
+            .map(|weight| weight.trim().parse::<u8>())
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| RuleParseError::UnknownNeighbourMethod(text.into()))?;
+        return match weights[..] {
+            [face, edge, corner] => Ok(NeighbourMethod::WeightedMoore { face, edge, corner }),
+            _ => Err(RuleParseError::UnknownNeighbourMethod(text.into())),
+        };
+    }
+
+    let (method, radius) = text.split_at(
+        text.find(|c: char| c.is_ascii_digit()).unwrap_or(text.len()));
+    let radius = match radius {
+        "" => 1,
+        radius => match radius.parse::<u8>() {
+            Ok(radius) if (1..=MAX_RADIUS).contains(&radius) => radius,
+            _ => return Err(RuleParseError::UnknownNeighbourMethod(text.into())),
+        },
+    };
+    match method {
+        "M" => Ok(NeighbourMethod::Moore(radius)),
+        "N" | "VN" => Ok(NeighbourMethod::VonNeuman(radius)),
+        "P" if radius == 1 && text == "P" => Ok(NeighbourMethod::Planar),
+        _ => Err(RuleParseError::UnknownNeighbourMethod(text.into())),
+    }
+}
+
+impl Rule {
+    // eg: "B4/S4/5/M" (birth/survival/states/neighbourhood).
+    // birth & survival are comma lists of counts or ranges, eg: "4,6,8-10".
+    // the neighbourhood is M (moore) or N (von neumann), optionally followed by
+    // the radius, eg: "M2". or W (weighted moore) with the face, edge & corner
+    // weights, eg: "W3,2,1".
+    // the boundary mode isn't part of the rulestring and defaults to Wrap.
+    pub fn from_rulestring(text: &str) -> Result<Rule, RuleParseError> {
+        let parts: Vec<&str> = text.trim().split('/').map(str::trim).collect();
+        if parts.len() != 4 {
+            return Err(RuleParseError::InvalidFormat);
+        }
+
+        let birth_rule = match parts[0].strip_prefix('B') {
+            Some(birth) => Value::parse(birth)?,
+            None => return Err(RuleParseError::InvalidFormat),
+        };
+        let survival_rule = match parts[1].strip_prefix('S') {
+            Some(survival) => Value::parse(survival)?,
+            None => return Err(RuleParseError::InvalidFormat),
+        };
+
+        let states = match parts[2].parse::<u8>() {
+            Ok(states) if states > 0 => states,
+            _ => return Err(RuleParseError::InvalidStates(parts[2].into())),
+        };
+
+        let neighbour_method = parse_neighbour_method(parts[3])?;
+
+        Ok(Rule {
+            survival_rule,
+            birth_rule,
+            states,
+            neighbour_method,
+            ..Default::default()
+        })
+    }
+
+    // a plausible rule, not a uniformly random one: births need a few
+    // neighbors, but never 0 (that floods the grid), and survival is a wider
+    // band starting at or below the births. mostly moore r1, where most of
+    // the known interesting rules are.
+    pub fn random(seed: u64) -> Rule {
+        let mut rand = StdRng::seed_from_u64(seed);
+        let neighbour_method = match rand.gen_range(0..10) {
+            0..=6 => NeighbourMethod::Moore(1),
+            7..=8 => NeighbourMethod::VonNeuman(1),
+            _     => NeighbourMethod::Moore(2),
+        };
+        let max = neighbour_method.max_neighbours();
+
+        let birth_start = rand.gen_range(1..=(max/4).max(1));
+        let birth_end   = (birth_start + rand.gen_range(0..=2)).min(max);
+        let survival_start = rand.gen_range(0..=birth_start);
+        let survival_end   = (survival_start + rand.gen_range(1..=(max/3).max(1))).min(max);
+
+        Rule {
+            survival_rule: Value::from_range(survival_start..=survival_end),
+            birth_rule: Value::from_range(birth_start..=birth_end),
+            states: rand.gen_range(2..=10),
+            neighbour_method,
+            ..Default::default()
+        }
+    }
+
+    pub fn to_rulestring(&self) -> String {
+        format!("B{}/S{}/{}/{}",
+            self.birth_rule.to_rulestring(),
+            self.survival_rule.to_rulestring(),
+            self.states,
+            neighbour_method_to_rulestring(&self.neighbour_method))
+    }
+
+    // the offsets & weights a full cell adds itself to, so its own position
+    // too with count_self.
+    pub fn neighbours(&self) -> impl Iterator<Item = (IVec3, u16)> + '_ {
+        let own = self.count_self.then(|| (IVec3::ZERO, 1));
+        self.neighbour_method.weighted_neighbours().chain(own)
+    }
+
+    // true if a grid without live cells stays without them, ie: nothing is
+    // born from 0 neighbors.
+    pub fn keeps_empty(&self) -> bool {
+        if let Some(margolus) = &self.margolus {
+            return margolus.next(0) == 0;
+        }
+        match &self.transitions {
+            Some(transitions) => transitions.next(0, 0) == 0,
+            None => !self.birth_rule.in_range(0),
+        }
+    }
+
+    pub fn max_neighbours(&self) -> u16 {
+        self.neighbour_method.max_neighbours() + self.count_self as u16
+    }
+
+    // the rule the survival counts are counted with: neighbour_method
+    // replaced by the survival neighbourhood. None if survival counts with
+    // neighbour_method too, so the birth counts can be reused. otherwise a
+    // sim needs a second counter per cell (or recounts every update).
+    pub fn with_survival_neighbours(&self) -> Option<Rule> {
+        match self.survival_neighbour_method {
+            Some(method) if method != self.neighbour_method => Some(Rule {
+                neighbour_method: method,
+                survival_neighbour_method: None,
+                ..self.clone()
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn max_survival_neighbours(&self) -> u16 {
+        self.survival_neighbour_method.unwrap_or(self.neighbour_method).max_neighbours() + self.count_self as u16
+    }
+
+    // the furthest a cell's counts reach, for both neighbourhoods.
+    pub fn max_radius(&self) -> u8 {
+        let survival = self.survival_neighbour_method.unwrap_or(self.neighbour_method);
+        self.neighbour_method.radius().max(survival.radius())
+    }
+
+    // where a seeded cell (eg: noise) at `pos` goes. like the boundary mode,
+    // but planar rules keep everything in the center z plane.
+    pub fn seed_pos(&self, pos: IVec3, bounds: IVec3) -> Option<IVec3> {
+        let pos =
+            if self.neighbour_method.is_planar() { IVec3::new(pos.x, pos.y, bounds.z/2) }
+            else { pos };
+        self.boundary_mode.resolve(pos, bounds)
+    }
+
+    // radius of the noise cube around the center of `bounds`. up to
+    // NOISE_RADIUS, but axes that don't wrap keep the cube at least a
+    // neighbourhood radius away from the boundary, so small grids don't get
+    // noise that's clamped or cut off at the edges.
+    pub fn noise_radius(&self, bounds: IVec3) -> i32 {
+        let wraps = match self.boundary_mode {
+            BoundaryMode::Wrap => BVec3::new(true, true, true),
+            BoundaryMode::WrapAxes(axes) => axes,
+            BoundaryMode::Clamp | BoundaryMode::Dead => BVec3::new(false, false, false),
+        };
+        // planar rules seed a single z plane, see seed_pos.
+        let wraps = BVec3::new(wraps.x, wraps.y, wraps.z || self.neighbour_method.is_planar());
+
+        let margin = self.neighbour_method.radius() as i32;
+        let center = utils::center(bounds);
+        // the cube is center-r..=center+r.
+        let fits = (center - IVec3::splat(margin)).min(bounds - IVec3::ONE - IVec3::splat(margin) - center);
+        let mut radius = utils::NOISE_RADIUS;
+        if !wraps.x { radius = radius.min(fits.x); }
+        if !wraps.y { radius = radius.min(fits.y); }
+        if !wraps.z { radius = radius.min(fits.z); }
+        radius.max(0)
+    }
+
+    // stable across runs (unlike DefaultHasher), so it can be written to disk.
+    pub fn fingerprint(&self) -> u64 {
+        let mut bytes = vec![];
+        bytes.extend(self.survival_rule.0.iter().map(|b| *b as u8));
+        bytes.extend(self.birth_rule.0.iter().map(|b| *b as u8));
+        bytes.push(self.states);
+        bytes.push(match self.neighbour_method {
+            NeighbourMethod::Moore(_) => 0,
+            NeighbourMethod::VonNeuman(_) => 1,
+            NeighbourMethod::WeightedMoore { .. } => 2,
+            NeighbourMethod::Planar => 3,
+        });
+        bytes.push(self.neighbour_method.radius());
+        if let NeighbourMethod::WeightedMoore { face, edge, corner } = self.neighbour_method {
+            bytes.extend([face, edge, corner]);
+        }
+        if let Some(transitions) = &self.transitions {
+            bytes.extend(transitions.next.iter());
+        }
+        if let Some(margolus) = &self.margolus {
+            bytes.extend(margolus.next.iter());
+        }
+        match self.boundary_mode {
+            BoundaryMode::Wrap => bytes.push(0),
+            BoundaryMode::Clamp => bytes.push(1),
+            BoundaryMode::Dead => bytes.push(2),
+            BoundaryMode::WrapAxes(axes) => bytes.extend([3, axes.x as u8, axes.y as u8, axes.z as u8]),
+        }
+        // only when set, so the fingerprints from before count_self stay the same.
+        if self.count_self {
+            bytes.push(1);
+        }
+        // same for the death mode.
+        if self.death_mode == DeathMode::Instant {
+            bytes.push(2);
+        }
+        // and the symmetry.
+        if let Some(symmetry) = self.enforce_symmetry {
+            bytes.extend([4, symmetry as u8]);
+        }
+        // and the survival neighbourhood.
+        if let Some(survival) = self.with_survival_neighbours() {
+            bytes.push(3);
+            bytes.extend(neighbour_method_to_rulestring(&survival.neighbour_method).bytes());
+        }
+
+        utils::fnv1a(bytes)
+    }
+}
+
+
+fn deserialize_value<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    Value::parse(&text).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_neighbour_method<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NeighbourMethod, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_neighbour_method(text.trim()).map_err(serde::de::Error::custom)
+}
+
+// the last rulestring part, eg: "M", "N2", "W3,2,1".
+fn neighbour_method_to_rulestring(method: &NeighbourMethod) -> String {
+    let radius = match method.radius() {
+        1 => String::new(),
+        radius => radius.to_string(),
+    };
+    match method {
+        NeighbourMethod::Moore(_) => format!("M{}", radius),
+        NeighbourMethod::VonNeuman(_) => format!("N{}", radius),
+        NeighbourMethod::WeightedMoore { face, edge, corner } =>
+            format!("W{},{},{}", face, edge, corner),
+        NeighbourMethod::Planar => "P".into(),
+    }
+}
+
+fn serialize_value<S: Serializer>(value: &Value, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.to_rulestring())
+}
+
+fn serialize_neighbour_method<S: Serializer>(method: &NeighbourMethod, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&neighbour_method_to_rulestring(method))
+}
+
+// skipped when None, see Rule.
+fn serialize_survival_neighbour_method<S: Serializer>(method: &Option<NeighbourMethod>, serializer: S) -> Result<S::Ok, S::Error> {
+    match method {
+        Some(method) => serialize_neighbour_method(method, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn deserialize_survival_neighbour_method<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<NeighbourMethod>, D::Error> {
+    deserialize_neighbour_method(deserializer).map(Some)
+}
+
+fn serialize_axes<S: Serializer>(axes: &BVec3, serializer: S) -> Result<S::Ok, S::Error> {
+    [axes.x, axes.y, axes.z].serialize(serializer)
+}
+
+fn deserialize_axes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BVec3, D::Error> {
+    let [x, y, z] = <[bool; 3]>::deserialize(deserializer)?;
+    Ok(BVec3::new(x, y, z))
+}
+
+fn default_boundary_mode() -> BoundaryMode {
+    BoundaryMode::Wrap
+}
+
+fn default_noise_density() -> f32 {
+    utils::DEFAULT_NOISE_DENSITY
+}
+
+fn default_seed_shape() -> SeedShape {
+    SeedShape::Cube
+}
+
+fn default_noise_kind() -> NoiseKind {
+    NoiseKind::Uniform
+}
+
+fn default_death_mode() -> DeathMode {
+    DeathMode::Decay
+}
+
+
+/*
+    rule files are toml, eg:
+        birth = "4"
+        survival = "4"
+        states = 5
+        neighbours = "M"
+        # optional:
+        boundary_mode = "wrap"
+        noise_density = 0.5
+        seed_shape = "sphere"   # cube, sphere or shell
+        noise_kind = { perlin = { frequency = 0.2, threshold = 0.1 } }   # or "uniform"
+        death_mode = "instant"   # or "decay"
+        color_method = "dist_to_center"
+        bounding_size = 64
+*/
+#[derive(Clone, Deserialize)]
+pub struct RuleFile {
+    #[serde(flatten)]
+    pub rule: Rule,
+    pub color_method: Option<ColorMethod>,
+    pub bounding_size: Option<i32>,
+}
+
+#[derive(Debug)]
+pub enum RuleFileError {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    Rule(RuleParseError),
+}
+
+impl std::fmt::Display for RuleFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RuleFileError::Io(error)   => write!(f, "{}", error),
+            RuleFileError::Toml(error) => write!(f, "{}", error),
+            RuleFileError::Rule(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for RuleFileError {}
+
+impl RuleFile {
+    pub fn from_toml_path<P: AsRef<Path>>(path: P) -> Result<RuleFile, RuleFileError> {
+        let text = std::fs::read_to_string(path).map_err(RuleFileError::Io)?;
+        let file: RuleFile = toml::from_str(&text).map_err(RuleFileError::Toml)?;
+        if file.rule.states == 0 {
+            return Err(RuleFileError::Rule(RuleParseError::InvalidStates("0".into())));
+        }
+        Ok(file)
+    }
+}
+
+// every rule file in a directory, sorted by file name. flipped through with
+// page up/down in the app.
+pub struct RuleLibrary {
+    // file stem & rule file.
+    pub entries: Vec<(String, RuleFile)>,
+}
+
+impl RuleLibrary {
+    // files that don't parse are reported and skipped, so one broken file
+    // doesn't hide the others.
+    pub fn from_dir<P: AsRef<Path>>(path: P) -> std::io::Result<RuleLibrary> {
+        let mut paths: Vec<_> = std::fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().map_or(false, |extension| extension == "toml"))
+            .collect();
+        paths.sort();
+
+        let mut entries = vec![];
+        for path in paths {
+            match RuleFile::from_toml_path(&path) {
+                Ok(file) => {
+                    let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+                    entries.push((name, file));
+                }
+                Err(error) => eprintln!("couldn't load rule file {:?}: {}", path, error),
+            }
+        }
+        Ok(RuleLibrary { entries })
+    }
+
+    pub fn empty() -> RuleLibrary {
+        RuleLibrary { entries: vec![] }
+    }
+}
+
+impl Rule {
+    // just the rule, see RuleFile for the format.
+    #[allow(dead_code)]
+    pub fn from_toml_path<P: AsRef<Path>>(path: P) -> Result<Rule, RuleFileError> {
+        RuleFile::from_toml_path(path).map(|file| file.rule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_moore() {
+        let rule = Rule::from_rulestring("B4/S4/5/M").unwrap();
+        assert_eq!(rule.neighbour_method, NeighbourMethod::Moore(1));
+        assert_eq!(rule.states, 5);
+        assert!(rule.birth_rule.in_range(4));
+        assert!(!rule.birth_rule.in_range(3));
+        assert!(rule.survival_rule.in_range(4));
+        assert_eq!(rule.to_rulestring(), "B4/S4/5/M");
+    }
+
+    #[test]
+    fn parse_von_neumann() {
+        let rule = Rule::from_rulestring("B4/S4/5/N").unwrap();
+        assert_eq!(rule.neighbour_method, NeighbourMethod::VonNeuman(1));
+        assert_eq!(rule.to_rulestring(), "B4/S4/5/N");
+    }
+
+    #[test]
+    fn rulestring_round_trip() {
+        for text in ["B4,6,8-10/S2-3/5/M2", "B1/S/2/N3", "B5-7/S6-8/4/W3,2,1", "B3/S2-3/2/P"] {
+            let rule = Rule::from_rulestring(text).unwrap();
+            assert_eq!(rule.to_rulestring(), text);
+            let again = Rule::from_rulestring(&rule.to_rulestring()).unwrap();
+            assert!(again == rule, "{} doesn't round trip", text);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        let cases = [
+            ("B4/S4/5", RuleParseError::InvalidFormat),
+            ("B4S4/5/M", RuleParseError::InvalidFormat),
+            ("4/S4/5/M", RuleParseError::InvalidFormat),
+            ("B4/4/5/M", RuleParseError::InvalidFormat),
+            ("Bx/S4/5/M", RuleParseError::InvalidNumber("x".into())),
+            ("B6-4/S4/5/M", RuleParseError::InvalidNumber("6-4".into())),
+            ("B343/S4/5/M", RuleParseError::NeighbourCountOutOfRange(343)),
+            ("B4/S4/0/M", RuleParseError::InvalidStates("0".into())),
+            ("B4/S4/x/M", RuleParseError::InvalidStates("x".into())),
+            ("B4/S4/5/Q", RuleParseError::UnknownNeighbourMethod("Q".into())),
+            ("B4/S4/5/M0", RuleParseError::UnknownNeighbourMethod("M0".into())),
+            ("B4/S4/5/W3,2", RuleParseError::UnknownNeighbourMethod("W3,2".into())),
+        ];
+        for (text, error) in cases {
+            assert_eq!(Rule::from_rulestring(text).err(), Some(error), "{}", text);
+        }
+    }
 }
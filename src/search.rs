@@ -0,0 +1,240 @@
+/*
+    how it works:
+        - simulated annealing over rules: each iteration mutates the current
+          rule a bit (one birth or survival count flipped, or one state more
+          or less), runs it headless from the same seeded noise with
+          run_until and scores the run, see score. lower is better.
+        - a better rule always replaces the current one, a worse one only
+          with a chance that shrinks with the difference and the temperature.
+          the temperature cools linearly to 0, so the search wanders early on
+          and only climbs at the end.
+        - the best rule so far is kept separately, that's what's reported.
+        - runs on its own thread with its own task pool, so the app doesn't
+          stall. the status is shared behind a mutex and polled by the ui.
+*/
+
+use bevy::{math::IVec3, tasks::TaskPool};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    cells::leddoo::{LeddooAtomic, RunOutcome},
+    rule::Rule,
+};
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+
+// score difference at which a worse rule is still taken half the time, at
+// the start. falls to 0 with the temperature.
+const START_TEMPERATURE: f32 = 0.5;
+
+const MAX_STATES: u8 = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchSettings {
+    // live cells wanted at the end of a run.
+    pub cell_count: usize,
+    // connected groups of live cells (26 neighbors) wanted at the end of a
+    // run. None doesn't care.
+    pub components: Option<usize>,
+    // generations per run. a run that dies out or fills up before that is
+    // scored worse, the earlier the worse.
+    pub generations: u64,
+    pub bounds: IVec3,
+    pub iterations: usize,
+    // for the noise & the mutations.
+    pub seed: u64,
+}
+
+impl Default for SearchSettings {
+    fn default() -> Self {
+        SearchSettings {
+            cell_count: 2000,
+            components: None,
+            generations: 200,
+            bounds: IVec3::splat(32),
+            iterations: 200,
+            seed: 42,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SearchStatus {
+    pub iteration: usize,
+    pub best_rule: Rule,
+    pub best_score: f32,
+    pub best_outcome: RunOutcome,
+    pub done: bool,
+}
+
+pub struct RuleSearch {
+    pub settings: SearchSettings,
+    status: Arc<Mutex<SearchStatus>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RuleSearch {
+    // starts from `rule`. transitions & margolus tables are dropped, the
+    // search only changes birth, survival & states.
+    pub fn start(rule: &Rule, settings: SearchSettings) -> RuleSearch {
+        let rule = Rule { margolus: None, transitions: None, ..rule.clone() };
+        let status = Arc::new(Mutex::new(SearchStatus {
+            iteration: 0,
+            best_rule: rule.clone(),
+            best_score: f32::INFINITY,
+            best_outcome: RunOutcome::MaxReached,
+            done: false,
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let status = status.clone();
+            let stop   = stop.clone();
+            std::thread::spawn(move || search(rule, settings, &status, &stop))
+        };
+        RuleSearch { settings, status, stop, thread: Some(thread) }
+    }
+
+    pub fn status(&self) -> SearchStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    // the best rule so far stays in status.
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+impl Drop for RuleSearch {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+
+fn search(rule: Rule, settings: SearchSettings, status: &Mutex<SearchStatus>, stop: &AtomicBool) {
+    let task_pool = TaskPool::new();
+    let mut sim = LeddooAtomic::new();
+    let mut rand = StdRng::seed_from_u64(settings.seed);
+
+    let (mut score, outcome) = evaluate(&mut sim, &rule, &settings, &task_pool);
+    let mut current = rule;
+    {
+        let mut status = status.lock().unwrap();
+        status.best_rule    = current.clone();
+        status.best_score   = score;
+        status.best_outcome = outcome;
+    }
+
+    for iteration in 0..settings.iterations {
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+
+        let candidate = mutate(&current, &mut rand);
+        let (candidate_score, outcome) = evaluate(&mut sim, &candidate, &settings, &task_pool);
+
+        let temperature = START_TEMPERATURE * (1.0 - iteration as f32 / settings.iterations as f32);
+        let accept = candidate_score <= score
+            || (temperature > 0.0
+                && rand.gen::<f32>() < 0.5f32.powf((candidate_score - score) / temperature));
+
+        let mut status = status.lock().unwrap();
+        status.iteration = iteration + 1;
+        if candidate_score < status.best_score {
+            status.best_rule    = candidate.clone();
+            status.best_score   = candidate_score;
+            status.best_outcome = outcome;
+        }
+        if accept {
+            current = candidate;
+            score   = candidate_score;
+        }
+    }
+
+    status.lock().unwrap().done = true;
+}
+
+// one birth count (never 0, see Rule::keeps_empty) or survival count flipped,
+// or the states moved by one.
+fn mutate(rule: &Rule, rand: &mut StdRng) -> Rule {
+    let mut rule = rule.clone();
+    let max = rule.max_neighbours();
+    match rand.gen_range(0..5) {
+        0..=1 => {
+            rule.birth_rule.toggle(rand.gen_range(1..=max.max(1)));
+            // a rule without births just dies out, not worth a run.
+            if rule.birth_rule.indices().next().is_none() {
+                rule.birth_rule.toggle(rand.gen_range(1..=max.max(1)));
+            }
+        }
+        2..=3 => rule.survival_rule.toggle(rand.gen_range(0..=max)),
+        _ => {
+            rule.states =
+                if rand.gen() { (rule.states + 1).min(MAX_STATES) }
+                else { rule.states.saturating_sub(1).max(1) };
+        }
+    }
+    rule
+}
+
+fn evaluate(sim: &mut LeddooAtomic, rule: &Rule, settings: &SearchSettings, tasks: &TaskPool) -> (f32, RunOutcome) {
+    sim.set_bounds(settings.bounds);
+    sim.spawn_noise(rule, Some(settings.seed));
+    let outcome = sim.run_until(rule, tasks, settings.generations);
+    (score(sim, outcome, settings), outcome)
+}
+
+// the relative errors of the cell count & components, plus the part of the
+// generations a run was cut short by dying out or filling up. a stable run
+// isn't cut short, it would go on like that.
+fn score(sim: &LeddooAtomic, outcome: RunOutcome, settings: &SearchSettings) -> f32 {
+    let relative_error = |value: usize, target: usize| {
+        (value as f32 - target as f32).abs() / target.max(1) as f32
+    };
+
+    let mut score = relative_error(sim.cell_count(), settings.cell_count);
+    if let Some(components) = settings.components {
+        score += relative_error(count_components(sim), components);
+    }
+    match outcome {
+        RunOutcome::Extinct(generation) | RunOutcome::Filled(generation) => {
+            score += 1.0 - generation as f32 / settings.generations.max(1) as f32;
+        }
+        RunOutcome::Stable(..) | RunOutcome::MaxReached => {}
+    }
+    score
+}
+
+// flood fill over the live cells. doesn't wrap around the bounds.
+fn count_components(sim: &LeddooAtomic) -> usize {
+    let mut unvisited: HashSet<IVec3> = sim.live_cells().map(|(pos, _)| pos).collect();
+    let mut components = 0;
+    let mut stack = vec![];
+    while let Some(&start) = unvisited.iter().next() {
+        unvisited.remove(&start);
+        stack.push(start);
+        while let Some(pos) = stack.pop() {
+            for z in -1..=1 {
+                for y in -1..=1 {
+                    for x in -1..=1 {
+                        let neighbor = pos + IVec3::new(x, y, z);
+                        if unvisited.remove(&neighbor) {
+                            stack.push(neighbor);
+                        }
+                    }
+                }
+            }
+        }
+        components += 1;
+    }
+    components
+}
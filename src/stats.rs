@@ -0,0 +1,66 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::rule::Rule;
+
+
+#[derive(Clone, Copy, Debug)]
+pub struct StatsRow {
+    pub generation: u64,
+    pub cell_count: usize,
+    // cells that entered / left the full state this generation.
+    pub births: usize,
+    pub deaths: usize,
+}
+
+// one row per update, for plotting population curves.
+pub struct StatsRecorder {
+    rows: Vec<StatsRow>,
+}
+
+impl StatsRecorder {
+    pub fn new() -> Self {
+        StatsRecorder { rows: vec![] }
+    }
+
+    pub fn record(&mut self, generation: u64, cell_count: usize, births: usize, deaths: usize) {
+        self.rows.push(StatsRow { generation, cell_count, births, deaths });
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.clear();
+    }
+
+    #[allow(dead_code)]
+    pub fn rows(&self) -> &[StatsRow] {
+        &self.rows
+    }
+
+    #[allow(dead_code)]
+    pub fn write_csv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "generation,cell_count,births,deaths")?;
+        for row in self.rows.iter() {
+            writeln!(w, "{},{},{},{}", row.generation, row.cell_count, row.births, row.deaths)?;
+        }
+        Ok(())
+    }
+}
+
+
+// what produced a run, written on exit (see sims.rs).
+#[derive(Serialize)]
+pub struct RunSummary<'a> {
+    pub rulestring: String,
+    pub rule: &'a Rule,
+    pub seed: Option<u64>,
+    pub generation: u64,
+    pub cell_count: usize,
+}
+
+impl<'a> RunSummary<'a> {
+    pub fn write_json<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *w, self)?;
+        writeln!(w)
+    }
+}
@@ -1,41 +1,190 @@
 use bevy::{
-    math::{ivec3, IVec3, Vec4},
+    math::{ivec3, vec3, BVec3, IVec3, Vec3, Vec4},
     prelude::Color,
 };
 use std::ops::RangeInclusive;
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
 
-pub fn is_in_bounds(pos: IVec3, bounds: i32) -> bool {
-    pos.x < bounds && pos.y < bounds && pos.z < bounds
+// bounds are the per-axis extents of the grid, positions are in 0..bounds.
+
+pub fn volume(bounds: IVec3) -> usize {
+    (bounds.x * bounds.y * bounds.z) as usize
+}
+
+pub fn is_in_bounds(pos: IVec3, bounds: IVec3) -> bool {
+    pos.x >= 0 && pos.y >= 0 && pos.z >= 0 &&
+    pos.x < bounds.x && pos.y < bounds.y && pos.z < bounds.z
+}
+
+pub fn wrap(pos: IVec3, bounds: IVec3) -> IVec3 {
+    // `%` is remainder and keeps negative values negative, rem_euclid is the
+    // modulo. so the result is in 0..bounds, however far out pos is (eg: large
+    // neighbourhood radii on small grids).
+    ivec3(
+        pos.x.rem_euclid(bounds.x),
+        pos.y.rem_euclid(bounds.y),
+        pos.z.rem_euclid(bounds.z))
 }
 
-pub fn wrap(pos: IVec3, bounds: i32) -> IVec3 {
-    // `%` is remainder and keeps negative values negative.
-    // we know that negative values are never below -bounds, so we can add
-    // bounds to get the modulo (wrapped result is in 0..bounds).
-    (pos + bounds) % bounds
+// like wrap, but only the axes in `axes`. the others are left as is, so they
+// can still be out of bounds.
+pub fn wrap_axes(pos: IVec3, bounds: IVec3, axes: BVec3) -> IVec3 {
+    let wrapped = wrap(pos, bounds);
+    ivec3(
+        if axes.x { wrapped.x } else { pos.x },
+        if axes.y { wrapped.y } else { pos.y },
+        if axes.z { wrapped.z } else { pos.z })
 }
 
-pub fn dist_to_center(cell_pos: IVec3, bounds: i32) -> f32 {
+// relative to the half extents, so 1.0 is the middle of a face.
+pub fn dist_to_center(cell_pos: IVec3, bounds: IVec3) -> f32 {
     let cell_pos = cell_pos - center(bounds);
-    let max = bounds as f32 / 2.0;
-    cell_pos.as_vec3().length() / max
+    let max = bounds.as_vec3() / 2.0;
+    (cell_pos.as_vec3() / max).length()
+}
+
+// same, but on a torus: each axis takes the shorter way around (the minimum
+// image), so positions outside the bounds get the distance of the cell they
+// wrap to.
+pub fn dist_to_center_wrapped(cell_pos: IVec3, bounds: IVec3) -> f32 {
+    let bounds = bounds.max(IVec3::ONE);
+    let half = bounds / 2;
+    let delta = wrap(cell_pos - center(bounds) + half, bounds) - half;
+    let max = bounds.as_vec3() / 2.0;
+    (delta.as_vec3() / max).length()
 }
 
-pub fn make_some_noise<F: FnMut(IVec3)>(center: IVec3, radius: i32, amount: usize, mut f: F) {
-    let mut rand = rand::thread_rng();
-    (0..amount).for_each(|_| {
-        f(center + ivec3(
-            rand.gen_range(-radius..=radius),
-            rand.gen_range(-radius..=radius),
-            rand.gen_range(-radius..=radius),
-        ));
-    });
+// which cells of the noise cube can be seeded.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeedShape {
+    Cube,
+    // the ball of `radius` around the center.
+    Sphere,
+    // only the outermost layer of the sphere, one cell thick.
+    Shell,
+}
+
+impl SeedShape {
+    pub fn contains(&self, offset: IVec3, radius: i32) -> bool {
+        let length_squared = offset.dot(offset);
+        match self {
+            SeedShape::Cube => true,
+            SeedShape::Sphere => length_squared <= radius*radius,
+            SeedShape::Shell =>
+                length_squared <= radius*radius
+                && length_squared > (radius - 1).max(0)*(radius - 1).max(0),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SeedShape::Cube => "Cube",
+            SeedShape::Sphere => "Sphere",
+            SeedShape::Shell => "Shell",
+        }
+    }
 }
 
-pub fn make_some_noise_default<F: FnMut(IVec3)>(center: IVec3, f: F) {
-    make_some_noise(center, 6, 12*12*12, f)
+// how the cells of the noise region are picked.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoiseKind {
+    // each cell on its own.
+    Uniform,
+    // cells where perlin noise (sampled at offset * frequency, so lower
+    // frequencies make larger blobs) is above the threshold, in -1..1. 0 is
+    // roughly half of the cells.
+    Perlin { frequency: f32, threshold: f32 },
+}
+
+impl NoiseKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            NoiseKind::Uniform => "Uniform",
+            NoiseKind::Perlin { .. } => "Perlin",
+        }
+    }
+}
+
+// every cell of `shape` in the cube of `radius` around `center` that `kind`
+// picks is seeded with a probability of `density`. so 0 spawns nothing, 1
+// fills the shape (or the perlin blobs).
+pub fn make_some_noise<R: Rng, F: FnMut(IVec3)>(rand: &mut R, center: IVec3, radius: i32, shape: SeedShape, kind: NoiseKind, density: f32, mut f: F) {
+    let perlin_seed = rand.gen::<u64>();
+    for z in -radius..=radius {
+        for y in -radius..=radius {
+            for x in -radius..=radius {
+                let offset = ivec3(x, y, z);
+                let picked = match kind {
+                    NoiseKind::Uniform => true,
+                    NoiseKind::Perlin { frequency, threshold } =>
+                        perlin(offset.as_vec3() * frequency, perlin_seed) > threshold,
+                };
+                // one draw per cell of the cube either way, so the same seed
+                // picks the same cells in each shape.
+                // gen is in 0..1, so density 1 always passes.
+                if rand.gen::<f32>() < density && picked && shape.contains(offset, radius) {
+                    f(center + offset);
+                }
+            }
+        }
+    }
+}
+
+// 3d gradient noise, roughly in -1..1 and 0 at integer positions. the
+// gradient of a lattice corner comes from hashing it with the seed, so there's
+// no permutation table.
+pub fn perlin(pos: Vec3, seed: u64) -> f32 {
+    // the cube's edge midpoints.
+    const GRADIENTS: [[f32; 3]; 12] = [
+        [1.0, 1.0, 0.0], [-1.0, 1.0, 0.0], [1.0, -1.0, 0.0], [-1.0, -1.0, 0.0],
+        [1.0, 0.0, 1.0], [-1.0, 0.0, 1.0], [1.0, 0.0, -1.0], [-1.0, 0.0, -1.0],
+        [0.0, 1.0, 1.0], [0.0, -1.0, 1.0], [0.0, 1.0, -1.0], [0.0, -1.0, -1.0],
+    ];
+
+    let cell = pos.floor();
+    let t = pos - cell;
+    let cell = cell.as_ivec3();
+    let corner = |x: i32, y: i32, z: i32| {
+        let corner = cell + ivec3(x, y, z);
+        let hash = fnv1a(seed.to_le_bytes().into_iter()
+            .chain(corner.x.to_le_bytes())
+            .chain(corner.y.to_le_bytes())
+            .chain(corner.z.to_le_bytes()));
+        Vec3::from(GRADIENTS[(hash % 12) as usize]).dot(t - vec3(x as f32, y as f32, z as f32))
+    };
+    // smootherstep, so the noise has no creases at the lattice.
+    let fade = |t: f32| t*t*t*(t*(t*6.0 - 15.0) + 10.0);
+    let (u, v, w) = (fade(t.x), fade(t.y), fade(t.z));
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a)*t;
+
+    lerp(
+        lerp(lerp(corner(0, 0, 0), corner(1, 0, 0), u), lerp(corner(0, 1, 0), corner(1, 1, 0), u), v),
+        lerp(lerp(corner(0, 0, 1), corner(1, 0, 1), u), lerp(corner(0, 1, 1), corner(1, 1, 1), u), v),
+        w)
+}
+
+pub const NOISE_RADIUS: i32 = 6;
+pub const DEFAULT_NOISE_DENSITY: f32 = 0.5;
+
+pub fn make_some_noise_in<F: FnMut(IVec3)>(center: IVec3, radius: i32, shape: SeedShape, kind: NoiseKind, density: f32, seed: Option<u64>, f: F) {
+    match seed {
+        Some(seed) => make_some_noise(&mut StdRng::seed_from_u64(seed), center, radius, shape, kind, density, f),
+        None => make_some_noise(&mut rand::thread_rng(), center, radius, shape, kind, density, f),
+    }
+}
+
+// same seed, same noise.
+#[allow(dead_code)]
+pub fn make_some_noise_seeded<F: FnMut(IVec3)>(center: IVec3, density: f32, seed: u64, f: F) {
+    make_some_noise_in(center, NOISE_RADIUS, SeedShape::Cube, NoiseKind::Uniform, density, Some(seed), f)
+}
+
+pub fn make_some_noise_default<F: FnMut(IVec3)>(center: IVec3, density: f32, seed: Option<u64>, f: F) {
+    make_some_noise_in(center, NOISE_RADIUS, SeedShape::Cube, NoiseKind::Uniform, density, seed, f)
 }
 
 pub fn lerp_color(color_1: Color, color_2: Color, dt: f32) -> Color {
@@ -45,33 +194,118 @@ pub fn lerp_color(color_1: Color, color_2: Color, dt: f32) -> Color {
     ((1.0 - dt)*color_1 + dt*color_2).into()
 }
 
+// FNV-1a. stable across runs & platforms, unlike std's hasher.
+pub fn fnv1a<I: IntoIterator<Item = u8>>(bytes: I) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// for ldr output. the shader passes colors through unchanged.
+pub fn clamp_color(color: Color) -> Color {
+    let color: Vec4 = color.into();
+    color.clamp(Vec4::ZERO, Vec4::ONE).into()
+}
+
+
+// per axis. 256^3 cells are already a few 100 mb in some sims.
+pub const MAX_BOUNDS: i32 = 256;
+
+// the bounds sims accept: 1..=MAX_BOUNDS per axis. eg: a rule file's
+// bounding_size of 0 would make an empty grid, a huge one runs out of memory.
+// what the sims' set_bounds use. Sim::reset goes through zero bounds, so
+// this doesn't complain.
+pub fn clamp_bounds_quiet(bounds: IVec3) -> IVec3 {
+    bounds.max(IVec3::ONE).min(IVec3::splat(MAX_BOUNDS))
+}
+
+// for bounds from the user or a rule file: says so if they were clamped.
+pub fn clamp_bounds(bounds: IVec3) -> IVec3 {
+    let clamped = clamp_bounds_quiet(bounds);
+    if clamped != bounds {
+        eprintln!("bounds {} are out of range, using {}", bounds, clamped);
+    }
+    clamped
+}
+
+pub fn div_ceil(a: usize, b: usize) -> usize {
+    (a + b - 1) / b
+}
 
-pub fn index_to_pos(index: usize, bounds: i32) -> IVec3 {
+pub fn index_to_pos(index: usize, bounds: IVec3) -> IVec3 {
     ivec3(
-        index as i32 % bounds,
-        index as i32 / bounds % bounds,
-        index as i32 / bounds / bounds)
+        index as i32 % bounds.x,
+        index as i32 / bounds.x % bounds.y,
+        index as i32 / bounds.x / bounds.y)
 }
 
-pub fn pos_to_index(pos: IVec3, bounds: i32) -> usize {
+pub fn pos_to_index(pos: IVec3, bounds: IVec3) -> usize {
     let x = pos.x as usize;
     let y = pos.y as usize;
     let z = pos.z as usize;
-    let bounds = bounds as usize;
-    x + y*bounds + z*bounds*bounds
+    let bounds_x = bounds.x as usize;
+    let bounds_y = bounds.y as usize;
+    x + y*bounds_x + z*bounds_x*bounds_y
 }
     
 
-pub fn get_bounding_ranges(bounds: i32)
+pub fn get_bounding_ranges(bounds: IVec3)
     -> (RangeInclusive<i32>, RangeInclusive<i32>, RangeInclusive<i32>)
 {
-    let x_range = 0..=bounds-1;
-    let y_range = 0..=bounds-1;
-    let z_range = 0..=bounds-1;
+    let x_range = 0..=bounds.x-1;
+    let y_range = 0..=bounds.y-1;
+    let z_range = 0..=bounds.z-1;
     (x_range, y_range, z_range)
 }
 
-pub fn center(bounds: i32) -> IVec3 {
-    let center = bounds/2;
-    ivec3(center, center, center)
+pub fn center(bounds: IVec3) -> IVec3 {
+    bounds/2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: IVec3 = IVec3::new(5, 7, 9);
+
+    #[test]
+    fn wrap_one_past_the_border() {
+        assert_eq!(wrap(ivec3(-1, 0, 0), BOUNDS), ivec3(BOUNDS.x - 1, 0, 0));
+        assert_eq!(wrap(ivec3(0, BOUNDS.y, 0), BOUNDS), ivec3(0, 0, 0));
+        assert_eq!(wrap(ivec3(1, 2, 3), BOUNDS), ivec3(1, 2, 3));
+    }
+
+    #[test]
+    fn wrap_several_periods_out() {
+        let b = BOUNDS;
+        assert_eq!(wrap(ivec3(-3*b.x - 1, 0, 0), b), ivec3(b.x - 1, 0, 0));
+        assert_eq!(wrap(ivec3(5*b.x + 2, 0, 0), b), ivec3(2, 0, 0));
+        assert_eq!(wrap(ivec3(0, -2*b.y, 4*b.z + 8), b), ivec3(0, 0, 8));
+        for pos in [ivec3(-100, 57, -3), ivec3(1000, -1000, 17)] {
+            assert!(is_in_bounds(wrap(pos, b), b));
+        }
+    }
+
+    #[test]
+    fn clamp_bounds_to_range() {
+        assert_eq!(clamp_bounds_quiet(IVec3::ZERO), IVec3::ONE);
+        assert_eq!(clamp_bounds_quiet(ivec3(0, 4, -2)), ivec3(1, 4, 1));
+        assert_eq!(clamp_bounds_quiet(ivec3(3, MAX_BOUNDS + 1, 7)), ivec3(3, MAX_BOUNDS, 7));
+        assert_eq!(clamp_bounds_quiet(BOUNDS), BOUNDS);
+        assert_eq!(clamp_bounds(ivec3(0, 4, -2)), clamp_bounds_quiet(ivec3(0, 4, -2)));
+    }
+
+    #[test]
+    fn wrap_axes_leaves_the_other_axes() {
+        let pos = ivec3(-1, BOUNDS.y + 2, -3*BOUNDS.z - 1);
+        assert_eq!(wrap_axes(pos, BOUNDS, BVec3::new(true, true, false)),
+            ivec3(BOUNDS.x - 1, 2, pos.z));
+        assert_eq!(wrap_axes(pos, BOUNDS, BVec3::new(false, false, true)),
+            ivec3(pos.x, pos.y, BOUNDS.z - 1));
+        assert_eq!(wrap_axes(pos, BOUNDS, BVec3::new(false, false, false)), pos);
+        assert_eq!(wrap_axes(pos, BOUNDS, BVec3::new(true, true, true)), wrap(pos, BOUNDS));
+    }
 }